@@ -59,3 +59,7 @@ import pycavy.dependencies as deps
 
 # The contents of the native extension
 from .pycavy import *
+from .benchmark import benchmark, compare_stats
+from .equivalence import formally_equivalent
+from .service import serve
+import pycavy.testing
@@ -0,0 +1,111 @@
+# A small harness for the experiment every compiler evaluation ends up
+# reproducing by hand: compile a corpus of programs under several
+# configurations and tabulate the resulting circuit metrics.
+
+import time
+from typing import Any, Dict, Iterable, List, Mapping, Tuple, Union
+
+from .pycavy import CavyError, Gate, Session, TDagGate, TGate
+
+Named = Union[Mapping[str, Any], Iterable[Any]]
+
+
+def _named(items: Named) -> List[Tuple[str, Any]]:
+    """Accept either a mapping from names to items, or a plain sequence, whose
+    items are then named by their index.
+    """
+    if isinstance(items, Mapping):
+        return [(str(name), item) for name, item in items.items()]
+    return [(str(i), item) for i, item in enumerate(items)]
+
+
+def circuit_metrics(gates) -> Dict[str, int]:
+    """Compute the gate count, depth, T-count, and qubit count of a compiled
+    circuit. Measurements count as gates; classical instructions don't.
+    """
+    gates = [gate for gate in gates if isinstance(gate, Gate)]
+    depths: Dict[int, int] = {}
+    t_count = 0
+    for gate in gates:
+        qbs = list(gate.qbs) + list(getattr(gate, 'ctrls', ()))
+        layer = 1 + max((depths.get(q, 0) for q in qbs), default=0)
+        for q in qbs:
+            depths[q] = layer
+        if isinstance(gate, (TGate, TDagGate)):
+            t_count += 1
+    return {
+        'gates': len(gates),
+        'depth': max(depths.values(), default=0),
+        't_count': t_count,
+        'qubits': len(depths),
+    }
+
+
+def benchmark(sources: Named, configs: Named) -> List[Dict[str, Any]]:
+    """Compile every source under every configuration, returning one record per
+    pair, suitable for constructing a `pandas.DataFrame`.
+
+    `sources` and `configs` may each be a mapping from names to values or a
+    plain sequence. Each configuration is a dictionary of `Session` keyword
+    arguments. The sources are compiled in parallel, with
+    `Session.compile_many`, and each record's `compile_time` is the time taken
+    to compile that source alone. Programs that fail to compile are recorded
+    with their error message, rather than aborting the whole run.
+    """
+    sources = _named(sources)
+    records = []
+    for config_name, config in _named(configs):
+        session = Session(**config)
+        # `compile_many` runs the `after` hooks in order of the sources
+        elapsed: List[float] = []
+        session.add_hook(after=lambda info: elapsed.append(info['elapsed']))
+        results = session.compile_many([source for _, source in sources])
+        for (source_name, _), result, compile_time in zip(
+            sources, results, elapsed
+        ):
+            record: Dict[str, Any] = {
+                'source': source_name,
+                'config': config_name,
+                'error': None,
+                'compile_time': compile_time,
+            }
+            if isinstance(result, CavyError):
+                record['error'] = str(result)
+            elif result is not None:
+                record.update(circuit_metrics(result))
+            records.append(record)
+    return records
+
+
+def compare_stats(
+    source: str, a: Mapping[str, Any], b: Mapping[str, Any]
+) -> Dict[str, Dict[str, Any]]:
+    """Compile a program under two configurations, each a dictionary of
+    `Session` keyword arguments, and compare the resulting circuits' metrics
+    and compile times. For each metric, the result holds both values, their
+    difference, and the percentage change from `a` to `b` (`None` if `a`'s
+    value is zero). A program that fails to compile raises its `CavyError`,
+    and a configuration that stops before building a circuit, with an earlier
+    `phase`, is compared as an empty circuit.
+    """
+    def measure(config):
+        session = Session(**config)
+        start = time.perf_counter()
+        circ = session.compile(source)
+        metrics: Dict[str, Any] = circuit_metrics(circ or ())
+        metrics['compile_time'] = time.perf_counter() - start
+        return metrics
+
+    metrics_a = measure(a)
+    metrics_b = measure(b)
+    report = {}
+    for metric, value_a in metrics_a.items():
+        value_b = metrics_b[metric]
+        delta = value_b - value_a
+        report[metric] = {
+            'a': value_a,
+            'b': value_b,
+            'delta': delta,
+            'percent': 100 * delta / value_a if value_a else None,
+        }
+    return report
@@ -65,6 +65,12 @@ DEPENDENCIES = {
         url='http://numpy.org',
         desc="""Python's de facto official numerical package"""
     ),
+    'mqt.qcec': DependencySpec(
+        name='mqt.qcec',
+        kind=DependencyKind.PYTHON_PKG,
+        url='https://mqt.readthedocs.io/projects/qcec/',
+        desc="""A formal equivalence checker for quantum circuits"""
+    ),
     '__unsatisfiable__': DependencySpec(
         name='unsatisfiable',
         kind=DependencyKind.UNSATISFIABLE,
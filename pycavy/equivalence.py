@@ -0,0 +1,38 @@
+# Formal equivalence checking of compiled circuits, by way of an external
+# checker. Circuits are handed over as the OpenQASM 2 written by `to_qcec`.
+
+import os
+import tempfile
+
+import pycavy.dependencies as deps
+
+# The verdicts under which two circuits count as equivalent
+_EQUIVALENT = ('equivalent', 'equivalent_up_to_global_phase')
+_NOT_EQUIVALENT = ('not_equivalent',)
+
+
+@deps.require('mqt.qcec')
+def formally_equivalent(a, b) -> bool:
+    """Check, using MQT QCEC, whether two compiled circuits implement the same
+    operation, up to a global phase. Raises a `RuntimeError` if the checker
+    can't reach a verdict either way.
+    """
+    from mqt import qcec
+
+    with tempfile.TemporaryDirectory() as tmp:
+        paths = []
+        for name, circ in (('a', a), ('b', b)):
+            path = os.path.join(tmp, name + '.qasm')
+            with open(path, 'w') as f:
+                f.write(circ.to_qcec())
+            paths.append(path)
+        result = qcec.verify(*paths)
+
+    verdict = str(result.equivalence).split('.')[-1]
+    if verdict in _EQUIVALENT:
+        return True
+    if verdict in _NOT_EQUIVALENT:
+        return False
+    raise RuntimeError(
+        'equivalence checker reached no verdict: {}'.format(verdict)
+    )
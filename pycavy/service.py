@@ -0,0 +1,262 @@
+# A compile server in a subprocess, so that a program that crashes the
+# compiler, or one that can't be trusted not to, takes down only the server
+# and not the interpreter using it. The server speaks JSON-RPC 2.0 over a Unix
+# socket, one request or response per line, and circuits travel in the schema
+# of `Circuit.to_json`.
+
+import itertools
+import json
+import os
+import socket
+import subprocess
+import sys
+import threading
+import time
+from typing import Any, Dict, Optional
+
+from .pycavy import CavyError, CavyTimeoutError, Circuit, Session
+
+# The JSON-RPC error codes: those the protocol reserves, and the server's own
+# for the errors compiling can raise
+_PARSE_ERROR = -32700
+_INVALID_REQUEST = -32600
+_METHOD_NOT_FOUND = -32601
+_INVALID_PARAMS = -32602
+_COMPILE_ERROR = 1
+_TIMEOUT = 2
+
+
+class _RequestError(Exception):
+    def __init__(self, code: int, message: str):
+        super().__init__(message)
+        self.code = code
+
+
+def _compile(sessions: Dict[str, Session], params: Any) -> Dict[str, Any]:
+    """The `compile` method: compile `source` in a session with `options`,
+    waiting at most `timeout` seconds
+    """
+    if not isinstance(params, dict) or not isinstance(
+        params.get('source'), str
+    ):
+        raise _RequestError(_INVALID_PARAMS, 'compile needs a source string')
+    options = params.get('options', {})
+    key = json.dumps(options, sort_keys=True)
+    try:
+        if key not in sessions:
+            sessions[key] = Session(**options)
+        circ = sessions[key].compile(
+            params['source'], timeout=params.get('timeout')
+        )
+    except CavyTimeoutError as err:
+        raise _RequestError(_TIMEOUT, str(err))
+    except CavyError as err:
+        raise _RequestError(_COMPILE_ERROR, str(err))
+    except (TypeError, ValueError) as err:
+        raise _RequestError(_INVALID_PARAMS, str(err))
+    return {'circuit': None if circ is None else circ.to_json()}
+
+
+def _respond(sessions: Dict[str, Session], line: bytes) -> Dict[str, Any]:
+    request_id = None
+    try:
+        try:
+            request = json.loads(line)
+        except ValueError as err:
+            raise _RequestError(_PARSE_ERROR, str(err))
+        if not isinstance(request, dict) or 'method' not in request:
+            raise _RequestError(_INVALID_REQUEST, 'not a JSON-RPC request')
+        request_id = request.get('id')
+        if request['method'] != 'compile':
+            raise _RequestError(
+                _METHOD_NOT_FOUND,
+                'no method {!r}'.format(request['method']),
+            )
+        result = _compile(sessions, request.get('params'))
+    except _RequestError as err:
+        return {
+            'jsonrpc': '2.0',
+            'id': request_id,
+            'error': {'code': err.code, 'message': str(err)},
+        }
+    return {'jsonrpc': '2.0', 'id': request_id, 'result': result}
+
+
+def main(socket_path: str):
+    """Serve compilations on a Unix socket at `socket_path` until killed,
+    handling one connection at a time
+    """
+    sessions: Dict[str, Session] = {}
+    server = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM)
+    server.bind(socket_path)
+    server.listen()
+    while True:
+        conn, _ = server.accept()
+        with conn, conn.makefile('rwb') as stream:
+            for line in stream:
+                response = _respond(sessions, line)
+                stream.write(json.dumps(response).encode() + b'\n')
+                stream.flush()
+
+
+# What the server's interpreter runs, given the socket path
+_SERVER = 'import sys; from pycavy.service import main; main(sys.argv[1])'
+
+
+class RemoteSession:
+    """A session whose programs are compiled by a `CompileServer`, taking the
+    same options as `Session`. Its `compile` returns circuits read back from
+    JSON, so their `session` is `None`.
+    """
+
+    def __init__(self, server: 'CompileServer', **options):
+        self.server = server
+        self.options = options
+
+    def compile(self, src, timeout: Optional[float] = None):
+        """Compile Cavy source, given as text, bytes, or a path, as
+        `Session.compile` would
+        """
+        return self.server.compile(src, timeout=timeout, **self.options)
+
+
+class CompileServer:
+    """A compile server in a subprocess, listening at `socket_path`. A
+    compilation that kills the server raises a `CavyError`, and the next one
+    starts a new server. The server compiles one program at a time, so
+    threads sharing a client wait their turn.
+    """
+
+    def __init__(self, socket_path: str, startup_timeout: float = 10):
+        self.socket_path = os.fspath(socket_path)
+        self.startup_timeout = startup_timeout
+        self._process: Optional[subprocess.Popen] = None
+        self._stream = None
+        self._ids = itertools.count()
+        self._lock = threading.Lock()
+        self._start()
+
+    def _start(self):
+        if os.path.exists(self.socket_path):
+            os.remove(self.socket_path)
+        # The server imports this same copy of pycavy
+        package_dir = os.path.dirname(os.path.dirname(__file__))
+        path = os.environ.get('PYTHONPATH')
+        env = dict(os.environ, PYTHONPATH=os.pathsep.join(
+            [package_dir] + ([path] if path else [])
+        ))
+        self._process = subprocess.Popen(
+            [sys.executable, '-c', _SERVER, self.socket_path], env=env,
+        )
+        deadline = time.monotonic() + self.startup_timeout
+        while True:
+            conn = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM)
+            try:
+                conn.connect(self.socket_path)
+                break
+            except (FileNotFoundError, ConnectionRefusedError):
+                conn.close()
+                if self._process.poll() is not None:
+                    self._process = None
+                    raise CavyError('the compile server failed to start')
+                if time.monotonic() > deadline:
+                    self._stop()
+                    raise CavyError('the compile server did not start')
+                time.sleep(0.01)
+        self._stream = conn.makefile('rwb')
+        conn.close()
+
+    def _crashed(self) -> CavyError:
+        self._stream.close()
+        self._stream = None
+        status = self._process.wait()
+        self._process = None
+        if status < 0:
+            how = 'killed by signal {}'.format(-status)
+        else:
+            how = 'exiting with status {}'.format(status)
+        return CavyError('the compile server crashed, {}'.format(how))
+
+    @property
+    def pid(self) -> Optional[int]:
+        """The server's process ID, or `None` between a crash and the next
+        compilation
+        """
+        return None if self._process is None else self._process.pid
+
+    def session(self, **options) -> RemoteSession:
+        """A session with `options`, as for `Session`, that compiles here"""
+        return RemoteSession(self, **options)
+
+    def compile(self, src, timeout: Optional[float] = None, **options):
+        """Compile Cavy source in a session with `options`, returning what
+        `Session.compile` would, or raising the `CavyError` it would
+        """
+        if isinstance(src, bytes):
+            src = src.decode()
+        elif not isinstance(src, str):
+            with open(src) as f:
+                src = f.read()
+        request_id = next(self._ids)
+        request = {
+            'jsonrpc': '2.0',
+            'id': request_id,
+            'method': 'compile',
+            'params': {'source': src, 'options': options, 'timeout': timeout},
+        }
+        with self._lock:
+            if self._process is None:
+                self._start()
+            try:
+                self._stream.write(json.dumps(request).encode() + b'\n')
+                self._stream.flush()
+                line = self._stream.readline()
+            except OSError:
+                line = b''
+            if not line:
+                raise self._crashed()
+        response = json.loads(line)
+        assert response['id'] == request_id
+        if 'error' in response:
+            code, message = (
+                response['error']['code'], response['error']['message']
+            )
+            if code == _TIMEOUT:
+                raise CavyTimeoutError(message)
+            if code == _COMPILE_ERROR:
+                raise CavyError(message)
+            raise ValueError(message)
+        circ = response['result']['circuit']
+        return None if circ is None else Circuit.from_json(circ)
+
+    def close(self):
+        """Stop the server"""
+        with self._lock:
+            self._stop()
+
+    def _stop(self):
+        if self._stream is not None:
+            self._stream.close()
+            self._stream = None
+        if self._process is not None:
+            self._process.kill()
+            self._process.wait()
+            self._process = None
+        if os.path.exists(self.socket_path):
+            os.remove(self.socket_path)
+
+    def __enter__(self) -> 'CompileServer':
+        return self
+
+    def __exit__(self, *exc):
+        self.close()
+
+
+def serve(socket_path: str) -> CompileServer:
+    """Start a compile server in a subprocess listening on a Unix socket at
+    `socket_path`, and return a client for it. Programs compiled through the
+    client, or through the `RemoteSession`s it makes, run in the server, so a
+    program that crashes the compiler raises a `CavyError` here instead of
+    taking down the interpreter. Call `close` to stop the server.
+    """
+    return CompileServer(socket_path)
@@ -0,0 +1,104 @@
+# Helpers for users writing regression tests against their own Cavy programs.
+
+import difflib
+import json
+import os
+import tempfile
+from typing import Callable, Dict, List
+
+from .pycavy import Circuit
+
+
+def serialize(circ: Circuit) -> str:
+    """The canonical text form of a circuit used for snapshots: one line per
+    instruction, holding its object from `Circuit.to_json`, which names every
+    field whatever the current repr style.
+    """
+    insts = json.loads(circ.to_json())['instructions']
+    return ''.join(
+        json.dumps(inst, sort_keys=True) + '\n' for inst in insts
+    )
+
+
+def assert_matches_snapshot(circ: Circuit, path: str, update: bool = False):
+    """Assert that a compiled circuit matches the snapshot stored at `path`,
+    raising an `AssertionError` with a unified diff otherwise.
+
+    If the snapshot doesn't exist yet, or `update` is true, the snapshot is
+    (re)written from `circ` instead.
+    """
+    actual = serialize(circ)
+    if update or not os.path.exists(path):
+        with open(path, 'w') as f:
+            f.write(actual)
+        return
+
+    with open(path) as f:
+        expected = f.read()
+    if actual != expected:
+        diff: List[str] = list(difflib.unified_diff(
+            expected.splitlines(keepends=True),
+            actual.splitlines(keepends=True),
+            fromfile=path,
+            tofile='compiled circuit',
+        ))
+        raise AssertionError(
+            'circuit does not match snapshot; rerun with update=True to '
+            'accept the changes:\n' + ''.join(diff)
+        )
+
+
+def _through_file(circ: Circuit) -> Circuit:
+    with tempfile.TemporaryDirectory() as tmp:
+        path = os.path.join(tmp, 'circuit')
+        circ.save(path)
+        return Circuit.load(path)
+
+
+# The formats `roundtrip` can check, each given by how to write a circuit out
+# and read it back in. The textual exports have no readers, so aren't here.
+_ROUNDTRIPS: Dict[str, Callable[[Circuit], Circuit]] = {
+    'json': lambda circ: Circuit.from_json(circ.to_json()),
+    'bytes': lambda circ: Circuit.from_bytes(circ.to_bytes()),
+    'file': _through_file,
+}
+
+
+def roundtrip(circ: Circuit, format: str = 'json') -> Circuit:
+    """Write a circuit out in an interchange `format` and read it back in,
+    asserting that every instruction comes back unchanged, and return the
+    circuit read back. The formats are `'json'`, through `Circuit.to_json`,
+    `'bytes'`, through `Circuit.to_bytes`, and `'file'`, through
+    `Circuit.save` and `Circuit.load`.
+    """
+    if format not in _ROUNDTRIPS:
+        raise ValueError(
+            'cannot round-trip through {!r}; formats are {}'.format(
+                format, ', '.join(_ROUNDTRIPS)
+            )
+        )
+    read = _ROUNDTRIPS[format](circ)
+    # The JSON schema names every field of every instruction, whatever the
+    # current repr style
+    before = json.loads(circ.to_json())
+    after = json.loads(read.to_json())
+    if after['n_qubits'] != before['n_qubits']:
+        raise AssertionError(
+            'circuit on {} qubits came back on {} from {}'.format(
+                before['n_qubits'], after['n_qubits'], format
+            )
+        )
+    insts, read_insts = before['instructions'], after['instructions']
+    for i, (inst, read_inst) in enumerate(zip(insts, read_insts)):
+        if inst != read_inst:
+            raise AssertionError(
+                'instruction {} changed in a round trip through {}: {} '
+                'became {}'.format(i, format, inst, read_inst)
+            )
+    if len(read_insts) != len(insts):
+        raise AssertionError(
+            'circuit of {} instructions came back with {} from {}'.format(
+                len(insts), len(read_insts), format
+            )
+        )
+    return read
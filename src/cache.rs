@@ -0,0 +1,84 @@
+//! A per-session cache of compiled circuits. A session's configuration never
+//! changes, so each circuit is keyed by its source alone.
+
+use std::collections::{HashMap, VecDeque};
+
+use pyo3::prelude::*;
+
+use crate::circuit::Circuit;
+
+/// The most recently used circuits, up to a fixed number of them. Circuits
+/// are immutable, so a cached one can be handed out again as it is.
+pub(crate) struct Cache {
+    max_size: usize,
+    circuits: HashMap<String, Option<Py<Circuit>>>,
+    /// Sources from least to most recently used
+    order: VecDeque<String>,
+    hits: u64,
+    misses: u64,
+}
+
+impl Cache {
+    pub(crate) fn new(max_size: usize) -> Self {
+        Self {
+            max_size,
+            circuits: HashMap::new(),
+            order: VecDeque::new(),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub(crate) fn enabled(&self) -> bool {
+        self.max_size > 0
+    }
+
+    /// The circuit compiled from this source, which may be `None`, if cached
+    pub(crate) fn get(&mut self, py: Python, src: &str) -> Option<Option<Py<Circuit>>> {
+        match self.circuits.get(src) {
+            Some(circ) => {
+                let circ = circ.as_ref().map(|circ| circ.clone_ref(py));
+                self.hits += 1;
+                if let Some(pos) = self.order.iter().position(|key| key == src) {
+                    let key = self.order.remove(pos).unwrap();
+                    self.order.push_back(key);
+                }
+                Some(circ)
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    pub(crate) fn insert(&mut self, src: String, circ: Option<Py<Circuit>>) {
+        if !self.enabled() || self.circuits.contains_key(&src) {
+            return;
+        }
+        while self.circuits.len() >= self.max_size {
+            match self.order.pop_front() {
+                Some(oldest) => self.circuits.remove(&oldest),
+                None => break,
+            };
+        }
+        self.order.push_back(src.clone());
+        self.circuits.insert(src, circ);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.circuits.clear();
+        self.order.clear();
+    }
+
+    /// The cached circuits, which refer back to the session holding the cache,
+    /// for the garbage collector to find the cycles they form
+    pub(crate) fn circuits(&self) -> impl Iterator<Item = &Py<Circuit>> {
+        self.circuits.values().flatten()
+    }
+
+    /// `(hits, misses, size, max_size)`
+    pub(crate) fn stats(&self) -> (u64, u64, usize, usize) {
+        (self.hits, self.misses, self.circuits.len(), self.max_size)
+    }
+}
@@ -0,0 +1,1057 @@
+//! Compiled circuits, as handed to Python, and their transcription from the
+//! compiler's instructions.
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
+
+use pyo3::{
+    class::{
+        basic::PyObjectProtocol, gc::PyGCProtocol, iter::PyIterProtocol,
+        mapping::PyMappingProtocol, sequence::PySequenceProtocol,
+    },
+    exceptions::{PyIndexError, PyTypeError, PyValueError},
+    prelude::*,
+    types::{PyBytes, PySlice},
+    PyTraverseError, PyVisit,
+};
+
+use cavy::circuit::{BaseGateC, BaseGateQ, Cbit, CircuitBuf, GateC, GateQ, Inst, Qbit};
+
+use crate::{
+    export::{
+        self,
+        provenance::{self, Provenance},
+        schedule::Timing,
+    },
+    feedback, fs_path,
+    gates::*,
+    interop,
+    mapped::MappedCircuit,
+    serialize, transform, Session,
+};
+
+/// A single instruction of a compiled circuit. This is the bindings' own copy
+/// of the compiler's `Inst`, from which Python objects are built on demand.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum Instruction {
+    Gate {
+        kind: GateKind,
+        qbs: Vec<usize>,
+        ctrls: Vec<usize>,
+    },
+    Meas {
+        qb: usize,
+        cb: usize,
+        mode: MeasMode,
+    },
+    CInit(usize),
+    CFree {
+        cb: usize,
+        flag: bool,
+    },
+    CGate {
+        kind: CGateKind,
+        cbs: Vec<usize>,
+        ctrls: Vec<usize>,
+    },
+    Out {
+        cb: usize,
+        name: String,
+        elem: usize,
+    },
+}
+
+fn qbit(u: Qbit) -> usize {
+    <u32>::from(u) as usize
+}
+
+fn cbit(u: Cbit) -> usize {
+    <u32>::from(u) as usize
+}
+
+impl Instruction {
+    fn from_gate(gate: GateQ) -> Self {
+        let (kind, qbs) = match gate.base {
+            BaseGateQ::X(u) => (GateKind::X, vec![u]),
+            BaseGateQ::T(u) => (GateKind::T, vec![u]),
+            BaseGateQ::H(u) => (GateKind::H, vec![u]),
+            BaseGateQ::Z(u) => (GateKind::Z, vec![u]),
+            BaseGateQ::TDag(u) => (GateKind::TDag, vec![u]),
+            BaseGateQ::Cnot { tgt, ctrl } => (GateKind::CX, vec![ctrl, tgt]),
+            BaseGateQ::Swap(fst, snd) => (GateKind::SWAP, vec![fst, snd]),
+        };
+        Self::Gate {
+            kind,
+            qbs: qbs.into_iter().map(qbit).collect(),
+            ctrls: gate.ctrls.iter().map(|&u| qbit(u)).collect(),
+        }
+    }
+
+    fn from_cgate(gate: GateC) -> Self {
+        let (kind, cbs) = match gate.base {
+            BaseGateC::Not(u) => (CGateKind::Not, vec![u]),
+            BaseGateC::Copy(src, dst) => (CGateKind::Copy, vec![src, dst]),
+            BaseGateC::Swap(fst, snd) => (CGateKind::Swap, vec![fst, snd]),
+        };
+        Self::CGate {
+            kind,
+            cbs: cbs.into_iter().map(cbit).collect(),
+            ctrls: gate.ctrls.iter().map(|&u| cbit(u)).collect(),
+        }
+    }
+
+    /// Transcribe a compiler instruction, if it isn't a qubit allocation or
+    /// free, which a `Circuit` keeps apart from its instructions
+    fn from_inst(inst: Inst, mode: MeasMode) -> Option<Self> {
+        let inst = match inst {
+            Inst::CInit(cb) => Self::CInit(cbit(cb)),
+            Inst::CFree(cb, flag) => Self::CFree { cb: cbit(cb), flag },
+            Inst::QInit(_) | Inst::QFree(_, _) => return None,
+            Inst::QGate(gate) => Self::from_gate(gate),
+            Inst::CGate(gate) => Self::from_cgate(gate),
+            Inst::Meas(qb, cb) => Self::Meas {
+                qb: qbit(qb),
+                cb: cbit(cb),
+                mode,
+            },
+            Inst::Out(out) => Self::Out {
+                cb: cbit(out.addr),
+                name: out.name,
+                elem: out.elem,
+            },
+        };
+        Some(inst)
+    }
+
+    /// The qubits this instruction acts on, controls first
+    pub(crate) fn qubits(&self) -> impl Iterator<Item = usize> + '_ {
+        let (fst, snd): (&[usize], &[usize]) = match self {
+            Self::Gate { qbs, ctrls, .. } => (ctrls, qbs),
+            Self::Meas { qb, .. } => (std::slice::from_ref(qb), &[]),
+            _ => (&[], &[]),
+        };
+        fst.iter().chain(snd).copied()
+    }
+
+    pub(crate) fn to_py(&self, py: Python) -> PyResult<PyObject> {
+        match self {
+            Self::Gate { kind, qbs, ctrls } => kind.pyobj(py, qbs, ctrls),
+            Self::Meas { qb, cb, mode } => MeasGate::pyobj(py, *qb, *cb, *mode),
+            Self::CInit(cb) => CInitGate::pyobj(py, *cb),
+            Self::CFree { cb, flag } => CFreeGate::pyobj(py, *cb, *flag),
+            Self::CGate { kind, cbs, ctrls } => {
+                CGate::pyobj(py, kind.name().to_owned(), cbs.clone(), ctrls.clone())
+            }
+            Self::Out { cb, name, elem } => OutGate::pyobj(py, *cb, name.clone(), *elem),
+        }
+    }
+}
+
+/// Instructions display in the compact repr style, independent of the style
+/// currently set for Python objects.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let style = ReprStyle::Compact;
+        let s = match self {
+            Self::Gate { kind, qbs, ctrls } => {
+                style.fmt_gate(kind.name(), kind.qasm_name(), qbs, ctrls)
+            }
+            Self::Meas { qb, cb, mode } => style.fmt_meas(*qb, *cb, *mode),
+            Self::CInit(cb) => style.fmt_classical("CInit", "CInitGate", &[("cb", cb)]),
+            Self::CFree { cb, flag } => {
+                style.fmt_classical("CFree", "CFreeGate", &[("cb", cb), ("flag", flag)])
+            }
+            Self::CGate { kind, cbs, ctrls } => style.fmt_classical(
+                "CGate",
+                "CGate",
+                &[("kind", &kind.name()), ("cbs", cbs), ("ctrls", ctrls)],
+            ),
+            Self::Out { cb, name, elem } => style.fmt_classical(
+                "Out",
+                "OutGate",
+                &[("cb", cb), ("name", name), ("elem", elem)],
+            ),
+        };
+        f.write_str(&s)
+    }
+}
+
+/// A compiled circuit: a sequence of instructions, which can be indexed and
+/// iterated over like a list of gate objects. Slicing it gives a `Circuit`.
+#[pyclass(gc)]
+pub(crate) struct Circuit {
+    insts: Vec<Instruction>,
+    /// The number of qubits allocated or acted on by the circuit
+    #[pyo3(get)]
+    n_qubits: usize,
+    /// The session, and therefore the configuration, that produced the circuit,
+    /// or `None` if it wasn't compiled in this process
+    #[pyo3(get)]
+    session: Option<Py<Session>>,
+    /// Python objects already handed out, if identical instructions should
+    /// share a single object. Gates have no setters, so this is safe.
+    interned: Option<RefCell<HashMap<Instruction, PyObject>>>,
+    /// The allocation intervals of each qubit, if the circuit came straight
+    /// from the compiler
+    lifetimes: Option<Lifetimes>,
+    /// Likewise, every qubit allocation and free, in order, with the index of
+    /// the instruction it comes just before
+    allocs: Option<Vec<(usize, Alloc)>>,
+    /// The fingerprint of the source the circuit was compiled from, if it was
+    /// compiled in this process
+    source: Option<u64>,
+}
+
+/// A qubit allocation or free, which `insts` leaves out
+#[derive(Clone, Copy)]
+pub(crate) enum Alloc {
+    Init(usize),
+    Free(usize, bool),
+}
+
+/// For each allocated qubit, the intervals of instruction indices during which
+/// it's allocated, as half-open `(alloc, free)` ranges, where `free` is `None`
+/// if it's never freed
+type Lifetimes = BTreeMap<usize, Vec<(usize, Option<usize>)>>;
+
+/// A circuit as transcribed from the compiler's instructions, before it
+/// belongs to any session, so that it can be sent from a child process that
+/// compiled it to the parent that's waiting for it
+pub(crate) struct Transcript {
+    pub(crate) insts: Vec<Instruction>,
+    pub(crate) n_qubits: usize,
+    /// As in `Circuit`
+    pub(crate) allocs: Vec<(usize, Alloc)>,
+}
+
+impl Transcript {
+    pub(crate) fn new(circ: CircuitBuf, mode: MeasMode) -> Self {
+        let mut n_qubits = 0;
+        let mut insts = vec![];
+        let mut allocs = vec![];
+        // What if there are infinitely many gates? See `GateIter`.
+        for inst in circ {
+            match &inst {
+                Inst::QInit(qb) => {
+                    n_qubits = n_qubits.max(qbit(*qb) + 1);
+                    allocs.push((insts.len(), Alloc::Init(qbit(*qb))));
+                }
+                Inst::QFree(qb, flag) => {
+                    allocs.push((insts.len(), Alloc::Free(qbit(*qb), *flag)));
+                }
+                _ => {}
+            }
+            if let Some(inst) = Instruction::from_inst(inst, mode) {
+                n_qubits = inst.qubits().map(|q| q + 1).fold(n_qubits, usize::max);
+                insts.push(inst);
+            }
+        }
+        Self {
+            insts,
+            n_qubits,
+            allocs,
+        }
+    }
+
+    fn lifetimes(&self) -> Lifetimes {
+        let mut lifetimes = Lifetimes::new();
+        for &(index, alloc) in &self.allocs {
+            match alloc {
+                Alloc::Init(qb) => lifetimes.entry(qb).or_default().push((index, None)),
+                Alloc::Free(qb, _) => {
+                    let interval = lifetimes.get_mut(&qb).and_then(|ivs| ivs.last_mut());
+                    if let Some((_, free @ None)) = interval {
+                        *free = Some(index);
+                    }
+                }
+            }
+        }
+        lifetimes
+    }
+}
+
+impl Circuit {
+    pub(crate) fn new(
+        py: Python,
+        circ: CircuitBuf,
+        src: &str,
+        session: Py<Session>,
+        intern: bool,
+    ) -> Self {
+        let mode = session.borrow(py).measurement_mode();
+        Self::from_transcript(Transcript::new(circ, mode), src, session, intern)
+    }
+
+    pub(crate) fn from_transcript(
+        transcript: Transcript,
+        src: &str,
+        session: Py<Session>,
+        intern: bool,
+    ) -> Self {
+        let lifetimes = transcript.lifetimes();
+        let Transcript {
+            insts,
+            n_qubits,
+            allocs,
+        } = transcript;
+        let mut circ = Self::from_insts(insts, n_qubits, Some(session), intern);
+        circ.lifetimes = Some(lifetimes);
+        circ.allocs = Some(allocs);
+        circ.source = Some(provenance::fingerprint(src));
+        circ
+    }
+
+    pub(crate) fn from_insts(
+        insts: Vec<Instruction>,
+        n_qubits: usize,
+        session: Option<Py<Session>>,
+        intern: bool,
+    ) -> Self {
+        Self {
+            insts,
+            n_qubits,
+            session,
+            interned: if intern {
+                Some(RefCell::default())
+            } else {
+                None
+            },
+            lifetimes: None,
+            allocs: None,
+            source: None,
+        }
+    }
+
+    pub(crate) fn n_qubits(&self) -> usize {
+        self.n_qubits
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.insts.len()
+    }
+
+    /// A new circuit with the same session, source and interning setting as
+    /// this one
+    fn with_insts(&self, py: Python, insts: Vec<Instruction>) -> Self {
+        let n_qubits = insts
+            .iter()
+            .flat_map(Instruction::qubits)
+            .map(|q| q + 1)
+            .fold(self.n_qubits, usize::max);
+        let session = self.session.as_ref().map(|session| session.clone_ref(py));
+        let mut circ = Self::from_insts(insts, n_qubits, session, self.interned.is_some());
+        circ.source = self.source;
+        circ
+    }
+
+    /// The provenance header of a textual export, in comments starting with
+    /// `comment`
+    fn header(&self, py: Python, comment: &str, timestamp: bool) -> String {
+        let provenance = Provenance {
+            config: self
+                .session
+                .as_ref()
+                .map(|session| session.borrow(py).fingerprint()),
+            source: self.source,
+        };
+        provenance::header(comment, &provenance, timestamp)
+    }
+
+    /// Get the Python object for the instruction at `index`, which must be in
+    /// bounds.
+    fn get(&self, py: Python, index: usize) -> PyResult<PyObject> {
+        let inst = &self.insts[index];
+        let interned = match &self.interned {
+            Some(interned) => interned,
+            None => return inst.to_py(py),
+        };
+        if let Some(obj) = interned.borrow().get(inst) {
+            return Ok(obj.clone_ref(py));
+        }
+        let obj = inst.to_py(py)?;
+        interned
+            .borrow_mut()
+            .insert(inst.clone(), obj.clone_ref(py));
+        Ok(obj)
+    }
+
+    /// Format every instruction with the given Python method, such as `__repr__`
+    fn fmt_insts(&self, method: &str) -> PyResult<Vec<String>> {
+        Python::with_gil(|py| {
+            (0..self.insts.len())
+                .map(|i| {
+                    self.get(py, i)?
+                        .call_method0(py, method)?
+                        .extract::<String>(py)
+                })
+                .collect()
+        })
+    }
+}
+
+#[pymethods]
+impl Circuit {
+    /// The intervals during which each qubit is allocated, as a dictionary
+    /// from qubits to lists of `(alloc, free)` pairs of instruction indices:
+    /// the qubit is allocated from just before instruction `alloc` until just
+    /// before instruction `free`, or `None` if it's never freed. Returns `None`
+    /// for circuits that didn't come straight from the compiler, such as those
+    /// built by `repeat` or read by `from_json`.
+    fn lifetimes(&self) -> Option<Lifetimes> {
+        self.lifetimes.clone()
+    }
+
+    /// The classical bits holding each of the program's outputs, as a
+    /// dictionary from output names to the bits of their `Out` instructions,
+    /// in order of element. The compiler's output doesn't say what Cavy type
+    /// an output has, so how its bits make up a value, such as the order of
+    /// an integer's bits, is up to the caller.
+    fn outputs(&self) -> BTreeMap<&str, Vec<usize>> {
+        let mut outputs: BTreeMap<&str, Vec<(usize, usize)>> = BTreeMap::new();
+        for inst in &self.insts {
+            if let Instruction::Out { cb, name, elem } = inst {
+                outputs.entry(name).or_default().push((*elem, *cb));
+            }
+        }
+        outputs
+            .into_iter()
+            .map(|(name, mut bits)| {
+                bits.sort_by_key(|&(elem, _)| elem);
+                (name, bits.into_iter().map(|(_, cb)| cb).collect())
+            })
+            .collect()
+    }
+
+    /// The circuit's classical feedback, as a dictionary from the index of each
+    /// instruction that depends on measurement results to the sorted indices
+    /// of the measurements it depends on. Only classical instructions read
+    /// classical bits, so every dependent instruction is a `CGate` reading a
+    /// measured bit, directly or through other classical gates, or an `Out`
+    /// of one. A bit's dependencies end when it's reinitialized or freed.
+    fn feedback_graph(&self) -> BTreeMap<usize, Vec<usize>> {
+        feedback::feedback_graph(&self.insts)
+    }
+
+    /// Every instruction, as iterating over the circuit gives them, with each
+    /// qubit allocation and free interleaved as a `QInitGate` or `QFreeGate`
+    /// where the compiler emitted it. Both extend `AllocInst`, rather than
+    /// `Gate` or `ClassicalInst`. Only circuits that came straight from the
+    /// compiler have allocations to include; for the rest, such as those
+    /// built by `repeat` or read by `from_json`, this is just `list(circuit)`.
+    fn instructions(&self, py: Python) -> PyResult<Vec<PyObject>> {
+        let no_allocs = vec![];
+        let mut allocs = self.allocs.as_ref().unwrap_or(&no_allocs).iter().peekable();
+        let mut objs = Vec::with_capacity(self.insts.len());
+        for i in 0..=self.insts.len() {
+            while let Some((_, alloc)) = allocs.next_if(|(index, _)| *index == i) {
+                objs.push(match *alloc {
+                    Alloc::Init(qb) => QInitGate::pyobj(py, qb)?,
+                    Alloc::Free(qb, flag) => QFreeGate::pyobj(py, qb, flag)?,
+                });
+            }
+            if i < self.insts.len() {
+                objs.push(self.get(py, i)?);
+            }
+        }
+        Ok(objs)
+    }
+
+    /// The gates and measurements of the circuit, which are the instructions
+    /// that extend `Gate`, without its classical instructions
+    #[getter]
+    fn gates(&self, py: Python) -> PyResult<Vec<PyObject>> {
+        (0..self.insts.len())
+            .filter(|&i| {
+                matches!(
+                    self.insts[i],
+                    Instruction::Gate { .. } | Instruction::Meas { .. }
+                )
+            })
+            .map(|i| self.get(py, i))
+            .collect()
+    }
+
+    /// Check that the circuit can be exported to a `target`, named by its
+    /// export method without the `to_` (`"qasm2"`, `"stim"`, `"qiskit"`, ...),
+    /// raising the `CavyError` listing every unrepresentable instruction that
+    /// the export would, but without exporting anything.
+    fn check_target(&self, target: &str) -> PyResult<()> {
+        export::check_target(&self.insts, self.n_qubits, target)
+    }
+
+    /// Serialize the circuit as an OpenQASM 2.0 program, with the qubit
+    /// register `q` and, if anything is measured, the classical register `c`.
+    ///
+    /// Like every textual export, the program starts with a comment header
+    /// giving the pycavy version, fingerprints of the session's compiler
+    /// options (`config`) and of the source (`source`) if the circuit was
+    /// compiled in this process, and the time it was `generated`, which is
+    /// left out with `timestamp=False` for reproducible output.
+    #[args(timestamp = "true")]
+    fn to_qasm2(&self, py: Python, timestamp: bool) -> PyResult<String> {
+        let qasm = export::qasm::to_qasm2(&self.insts, self.n_qubits)?;
+        Ok(self.header(py, "//", timestamp) + &qasm)
+    }
+
+    /// Serialize the circuit as an OpenQASM 3.0 program, with the qubit
+    /// register `q` and, if any classical bits are used, the bit register `c`.
+    /// Classical gates are written as assignments to bits of `c`, and those
+    /// with controls, such as the feedback in programs compiled with
+    /// `feedback=True`, as `if` statements on the controlling bits.
+    ///
+    /// Given a time `unit` (`"dt"`, `"ns"`, `"us"`, `"ms"`, or `"s"`) and the
+    /// `durations` of operations, as for `to_schedule_json`, each operation is
+    /// written in a `box` of its duration. The header and `timestamp` are as
+    /// for `to_qasm2`.
+    #[args(unit = "None", durations = "None", timestamp = "true")]
+    fn to_qasm3(
+        &self,
+        py: Python,
+        unit: Option<String>,
+        durations: Option<HashMap<String, f64>>,
+        timestamp: bool,
+    ) -> PyResult<String> {
+        let timing = Timing::new(&self.insts, unit, durations)?;
+        let qasm = export::qasm::to_qasm3(&self.insts, self.n_qubits, timing.as_ref())?;
+        Ok(self.header(py, "//", timestamp) + &qasm)
+    }
+
+    /// Serialize the circuit as OpenQASM 2.0 for a formal equivalence checker
+    /// such as MQT QCEC, which only accepts measurements at the end; see
+    /// `pycavy.formally_equivalent`.
+    fn to_qcec(&self) -> PyResult<String> {
+        export::qasm::to_qcec(&self.insts, self.n_qubits)
+    }
+
+    /// Serialize the circuit's as-soon-as-possible moment schedule as JSON.
+    /// Each moment has an integer `time`, and each operation lists the qubit
+    /// channels (`"q0"`, `"q1"`, ...) it occupies during that moment. A gate's
+    /// `qubits` are its targets, and its controls, even a CX's, are `ctrls`.
+    ///
+    /// Physical timing can be attached by giving both a time `unit` (`"dt"`,
+    /// `"ns"`, `"us"`, `"ms"`, or `"s"`) and a dictionary of `durations` in that
+    /// unit, keyed by operation name (`"h"`, `"cx"`, `"measure"`, ...), for
+    /// every operation the circuit uses. Controlled gates are named with a
+    /// `c` for each control, so that a Toffoli is a `"ccx"` and a controlled
+    /// Hadamard a `"ch"`. Each operation then has a `duration`, and each
+    /// moment a `start` and a `duration`, that of its longest operation.
+    #[args(unit = "None", durations = "None")]
+    fn to_schedule_json(
+        &self,
+        py: Python,
+        unit: Option<String>,
+        durations: Option<HashMap<String, f64>>,
+    ) -> PyResult<String> {
+        let timing = Timing::new(&self.insts, unit, durations)?;
+        export::schedule::to_schedule_json(py, &self.insts, self.n_qubits, timing.as_ref())
+    }
+
+    /// Estimate how long the circuit takes to run, in the time `unit`, given
+    /// the `durations` of operations as for `to_schedule_json`. This is the
+    /// length of the critical path, over which each operation starts as soon
+    /// as the operations before it on its qubits have finished, and so can be
+    /// shorter than the schedule's `duration`, in which every moment waits for
+    /// the one before. Classical instructions take no time.
+    fn estimated_runtime(&self, unit: String, durations: HashMap<String, f64>) -> PyResult<f64> {
+        let timing = Timing::new(&self.insts, Some(unit), Some(durations))?.unwrap();
+        Ok(export::schedule::critical_path(
+            &self.insts,
+            self.n_qubits,
+            &timing,
+        ))
+    }
+
+    /// Estimate the time, and optionally the cost, of running the circuit for
+    /// a number of `shots` on a device that can repeat it at most
+    /// `repetition_rate` times a second, given the `durations` of operations
+    /// as for `estimated_runtime`, but in a time `unit` other than `"dt"`.
+    ///
+    /// Returns a dictionary of the `shot_time`, the estimated runtime of each
+    /// shot, and the `total_time` of every shot, both in seconds; each shot
+    /// takes its runtime, or the time between repetitions if that's longer.
+    /// Given a price `cost_per_shot` and `cost_per_task`, as cloud providers
+    /// charge, it also holds the `cost` of the run, which is otherwise `None`.
+    #[args(cost_per_shot = "None", cost_per_task = "None")]
+    fn budget(
+        &self,
+        shots: u64,
+        repetition_rate: f64,
+        unit: String,
+        durations: HashMap<String, f64>,
+        cost_per_shot: Option<f64>,
+        cost_per_task: Option<f64>,
+    ) -> PyResult<HashMap<&'static str, Option<f64>>> {
+        if !(repetition_rate > 0.0 && repetition_rate.is_finite()) {
+            let msg = format!("repetition rate must be positive, not {}", repetition_rate);
+            return Err(PyValueError::new_err(msg));
+        }
+        for cost in cost_per_shot.iter().chain(&cost_per_task) {
+            if !(*cost >= 0.0 && cost.is_finite()) {
+                let msg = format!("costs must be nonnegative, not {}", cost);
+                return Err(PyValueError::new_err(msg));
+            }
+        }
+        let timing = Timing::new(&self.insts, Some(unit), Some(durations))?.unwrap();
+        let unit_seconds = timing.unit_seconds().ok_or_else(|| {
+            PyValueError::new_err("a budget needs durations in a unit of seconds, not dt")
+        })?;
+        let runtime = export::schedule::critical_path(&self.insts, self.n_qubits, &timing);
+        let shot_time = runtime * unit_seconds;
+        let cost = match (cost_per_shot, cost_per_task) {
+            (None, None) => None,
+            (per_shot, per_task) => {
+                Some(per_task.unwrap_or(0.0) + shots as f64 * per_shot.unwrap_or(0.0))
+            }
+        };
+        let total_time = shots as f64 * shot_time.max(1.0 / repetition_rate);
+        let mut budget = HashMap::new();
+        budget.insert("shot_time", Some(shot_time));
+        budget.insert("total_time", Some(total_time));
+        budget.insert("cost", cost);
+        Ok(budget)
+    }
+
+    /// Generate the source of a Python experiment scaffold for the circuit: an
+    /// `Experiment` class with a stub method for each operation used, and a
+    /// `run` method calling them in schedule order.
+    fn to_experiment_script(&self) -> PyResult<String> {
+        export::script::to_experiment_script(&self.insts, self.n_qubits)
+    }
+
+    /// Serialize the circuit as QIR, in textual LLVM IR, in which each measured
+    /// classical bit is a result, numbered in the order of the bits, and every
+    /// result is recorded as an output at the end. The program is marked as
+    /// following the base profile, unless it acts on a qubit after measuring
+    /// it, or measures into a bit twice, which only the adaptive profile
+    /// allows. The header and `timestamp` are as for `to_qasm2`.
+    #[args(timestamp = "true")]
+    fn to_qir(&self, py: Python, timestamp: bool) -> PyResult<String> {
+        let qir = export::qir::to_qir(&self.insts, self.n_qubits)?;
+        Ok(self.header(py, ";", timestamp) + &qir)
+    }
+
+    /// Serialize the circuit as a Quil program, with measurement outcomes
+    /// written to the declared `ro` register. The header and `timestamp` are
+    /// as for `to_qasm2`.
+    #[args(timestamp = "true")]
+    fn to_quil(&self, py: Python, timestamp: bool) -> PyResult<String> {
+        let quil = export::quil::to_quil(&self.insts)?;
+        Ok(self.header(py, "#", timestamp) + &quil)
+    }
+
+    /// Serialize the circuit as a Stim circuit. Every gate must be a Clifford
+    /// gate; otherwise, this raises an error listing the ones that aren't.
+    /// Stim records measurement outcomes in order, so classical bit indices
+    /// aren't preserved. The header and `timestamp` are as for `to_qasm2`.
+    #[args(timestamp = "true")]
+    fn to_stim(&self, py: Python, timestamp: bool) -> PyResult<String> {
+        let stim = export::stim::to_stim(&self.insts)?;
+        Ok(self.header(py, "#", timestamp) + &stim)
+    }
+
+    /// Serialize the circuit as JSON, for storage and exchange. The schema is
+    /// an object with a `version`, `n_qubits`, and a list of `instructions`,
+    /// each an object with a `kind` (`"gate"`, `"meas"`, `"c_init"`,
+    /// `"c_free"`, `"c_gate"`, or `"out"`) and its operands: `gate`, `qbs` and
+    /// `ctrls` for gates, `op`, `cbs` and `ctrls` for classical gates, and `qb`,
+    /// `cb`, `flag`, `name`, or `elem` otherwise. The session isn't included.
+    fn to_json(&self) -> PyResult<String> {
+        serialize::to_json(&self.insts, self.n_qubits)
+    }
+
+    /// Read a circuit written by `to_json`. The circuit has no `session`. JSON
+    /// that doesn't describe a valid circuit, such as a gate with the wrong
+    /// number of qubits, a qubit out of range, or an operand used twice in one
+    /// instruction, raises a `ValueError`.
+    #[staticmethod]
+    fn from_json(json: &str) -> PyResult<Self> {
+        let (insts, n_qubits) = serialize::from_json(json)?;
+        Ok(Self::from_insts(insts, n_qubits, None, false))
+    }
+
+    /// Serialize the circuit in a compact binary format, which is much
+    /// smaller and faster to read and write than `to_json`, but only
+    /// readable by `from_bytes`.
+    fn to_bytes<'p>(&self, py: Python<'p>) -> PyResult<&'p PyBytes> {
+        let bytes = serialize::to_bytes(&self.insts, self.n_qubits)?;
+        Ok(PyBytes::new(py, &bytes))
+    }
+
+    /// Read a circuit written by `to_bytes`. The circuit has no `session`.
+    #[staticmethod]
+    fn from_bytes(bytes: &[u8]) -> PyResult<Self> {
+        let (insts, n_qubits) = serialize::from_bytes(bytes)?;
+        Ok(Self::from_insts(insts, n_qubits, None, false))
+    }
+
+    /// Write the circuit to a file, in the format of `to_bytes` followed by an
+    /// index of the instructions, so that it can be loaded with `mmap=True`.
+    /// The file is written as the circuit is encoded, rather than after it's
+    /// encoded in full, so saving a large circuit needs no memory beyond the
+    /// circuit itself.
+    fn save(&self, py: Python, path: &PyAny) -> PyResult<()> {
+        serialize::save(&fs_path(py, path)?, &self.insts, self.n_qubits)
+    }
+
+    /// Read a circuit from a file written by `save`, or holding the output of
+    /// `to_bytes`. The circuit has no `session`.
+    ///
+    /// With `mmap=True`, the file, which must have been written by `save`, is
+    /// instead mapped into memory and returned as a `MappedCircuit`, which
+    /// decodes instructions only as they're used. The file mustn't then be
+    /// truncated or rewritten in place until that circuit is gone, or reading
+    /// from it may crash the process.
+    #[staticmethod]
+    #[args(mmap = "false")]
+    fn load(py: Python, path: &PyAny, mmap: bool) -> PyResult<PyObject> {
+        let path = fs_path(py, path)?;
+        if mmap {
+            let circ = MappedCircuit::new(serialize::Mapped::open(&path)?);
+            return Ok(Py::new(py, circ)?.into_py(py));
+        }
+        let (insts, n_qubits) = serialize::load(&path)?;
+        Ok(Self::from_insts(insts, n_qubits, None, false).into_py(py))
+    }
+
+    /// The circuit repeated `n` times, with the circuit `interleave`, if
+    /// given, between each repetition.
+    #[args(interleave = "None")]
+    fn repeat(&self, py: Python, n: usize, interleave: Option<PyRef<Circuit>>) -> Self {
+        let interleave = interleave.as_ref().map(|circ| &circ.insts[..]);
+        let insts = transform::sequence::repeat(&self.insts, n, interleave);
+        self.with_insts(py, insts)
+    }
+
+    /// The echo sequence of the circuit: the circuit, `idle_gate` on every
+    /// qubit, the circuit again, and `idle_gate` again. The idle gate may be
+    /// `"X"`, `"Z"`, or `"H"`.
+    #[args(idle_gate = "\"X\"")]
+    fn echo(&self, py: Python, idle_gate: &str) -> PyResult<Self> {
+        let insts = transform::sequence::echo(&self.insts, self.n_qubits, idle_gate)?;
+        Ok(self.with_insts(py, insts))
+    }
+
+    /// Randomized compiling: `instances` logically equivalent copies of the
+    /// circuit, in which each two-qubit Clifford gate is twirled by random
+    /// Paulis. The compensating Paulis are carried forward through Clifford
+    /// gates and absorbed by measurements where possible, and otherwise
+    /// written out before the next non-Clifford gate or at the end.
+    #[args(instances = "20", seed = "None")]
+    fn twirl(&self, py: Python, instances: usize, seed: Option<u64>) -> Vec<Self> {
+        let mut rng = transform::Rng::new(seed);
+        (0..instances)
+            .map(|_| {
+                let insts = transform::twirl::twirl(&self.insts, self.n_qubits, &mut rng);
+                self.with_insts(py, insts)
+            })
+            .collect()
+    }
+
+    /// A mirror circuit for benchmarking: the circuit, its inverse, a random
+    /// Pauli layer, and a measurement of every qubit into the classical bit of
+    /// the same index. Returns the mirror circuit and the bitstring, beginning
+    /// with qubit 0, that it measures when run without noise. The circuit must
+    /// contain only gates.
+    #[args(seed = "None")]
+    fn mirror(&self, py: Python, seed: Option<u64>) -> PyResult<(Self, String)> {
+        let mut rng = transform::Rng::new(seed);
+        let mode = match &self.session {
+            Some(session) => session.borrow(py).measurement_mode(),
+            None => MeasMode::default(),
+        };
+        let (insts, expected) =
+            transform::mirror::mirror(&self.insts, self.n_qubits, mode, &mut rng)?;
+        Ok((self.with_insts(py, insts), expected))
+    }
+
+    /// The subcircuit acting only on `qubits`, which keep their indices, with
+    /// every classical instruction kept. A gate acting on both `qubits` and
+    /// other qubits crosses the cut, and raises a `CavyError` listing every
+    /// such gate; with `cut=True`, these are instead dropped, and the result is
+    /// a pair of the subcircuit and the indices in this circuit of the gates
+    /// that were cut.
+    #[args(cut = "false")]
+    fn restrict(&self, py: Python, qubits: Vec<usize>, cut: bool) -> PyResult<PyObject> {
+        let (insts, cuts) =
+            transform::restrict::restrict(&self.insts, self.n_qubits, &qubits, cut)?;
+        let circ = self.with_insts(py, insts);
+        if cut {
+            Ok((circ, cuts).into_py(py))
+        } else {
+            Ok(circ.into_py(py))
+        }
+    }
+
+    /// The circuit with every gate removed that can't affect the Z-basis
+    /// measurement statistics of the qubits `observables` at its end: those
+    /// outside their light cone, and diagonal gates followed only by diagonal
+    /// gates and measurements. Measurements of qubits that don't affect the
+    /// observables are removed too, but classical instructions are kept.
+    fn prune_for(&self, py: Python, observables: Vec<usize>) -> PyResult<Self> {
+        let insts = transform::prune::prune_for(&self.insts, self.n_qubits, &observables)?;
+        Ok(self.with_insts(py, insts))
+    }
+
+    /// The circuit without the gates and measurements outside the causal past
+    /// of the qubits `measured_qubits` at its end, from which every gate that
+    /// can affect their final state, diagonal or not, is kept. Classical
+    /// instructions are kept too. For measurement statistics alone,
+    /// `prune_for` removes more.
+    fn light_cone(&self, py: Python, measured_qubits: Vec<usize>) -> PyResult<Self> {
+        let insts = transform::prune::light_cone(&self.insts, self.n_qubits, &measured_qubits)?;
+        Ok(self.with_insts(py, insts))
+    }
+
+    /// The circuit's gate count, depth, T-count, and qubit count, as a flat
+    /// dictionary with the keys of the metrics in `pycavy.benchmark` records,
+    /// so that `pandas.DataFrame([c.metrics_frame() for c in circuits])` builds
+    /// a table of them.
+    fn metrics_frame(slf: PyRef<Self>, py: Python) -> PyResult<PyObject> {
+        let benchmark = py.import("pycavy.benchmark")?;
+        Ok(benchmark.call1("circuit_metrics", (slf,))?.into())
+    }
+
+    /// Replay the circuit onto `engine`, any object with a method for each
+    /// operation the circuit uses, named as in OpenQASM: each gate calls
+    /// `engine.h(q)`, `engine.cx(c, t)`, `engine.tdg(q)`, and so on, with its
+    /// qubits, and with its control qubits as a `ctrls` list keyword argument
+    /// if it has any; each measurement calls `engine.measure(q)`. Classical
+    /// instructions are skipped. Raises a `TypeError`, before calling
+    /// anything, if the engine is missing any of the methods.
+    fn apply_to(&self, py: Python, engine: &PyAny) -> PyResult<()> {
+        interop::engine::apply_to(py, &self.insts, engine)
+    }
+
+    /// Convert the circuit to a `qiskit.QuantumCircuit`, with a quantum
+    /// register of `n_qubits` qubits and a classical register of one bit for
+    /// each classical bit used. Requires Qiskit to be installed.
+    fn to_qiskit(&self, py: Python) -> PyResult<PyObject> {
+        interop::qiskit::to_qiskit(py, &self.insts, self.n_qubits)
+    }
+
+    /// Convert the circuit to a `cirq.Circuit` on `cirq.LineQubit`s, in which
+    /// each measurement is keyed by its classical bit, as `"c0"`, `"c1"`, ...
+    /// Requires Cirq to be installed.
+    fn to_cirq(&self, py: Python) -> PyResult<PyObject> {
+        interop::cirq::to_cirq(py, &self.insts, self.n_qubits)
+    }
+
+    /// Convert the circuit to a `pytket.Circuit`, preserving qubit and
+    /// classical bit indices. Requires pytket to be installed.
+    fn to_tket(&self, py: Python) -> PyResult<PyObject> {
+        interop::tket::to_tket(py, &self.insts, self.n_qubits)
+    }
+
+    /// Convert the circuit to a Braket SDK `braket.circuits.Circuit`. Braket
+    /// records measurement outcomes by qubit, so classical bit indices aren't
+    /// preserved; for Braket's OpenQASM interface, use `to_qasm3` instead.
+    /// Braket also measures each qubit only once, at the end, so a circuit
+    /// with a mid-circuit or repeated measurement raises a `CavyError` listing
+    /// each instruction that acts on an already measured qubit. Requires the
+    /// Amazon Braket SDK to be installed.
+    fn to_braket(&self, py: Python) -> PyResult<PyObject> {
+        interop::braket::to_braket(py, &self.insts)
+    }
+
+    /// Convert the circuit to a `qulacs.QuantumCircuit`, for simulation on
+    /// Qulacs's CPU or GPU backends. Measurements are kept, storing each
+    /// outcome at its classical bit's index in the state's classical
+    /// register. Requires Qulacs to be installed.
+    fn to_qulacs(&self, py: Python) -> PyResult<PyObject> {
+        interop::qulacs::to_qulacs(py, &self.insts, self.n_qubits)
+    }
+}
+
+#[pyproto]
+impl PySequenceProtocol for Circuit {
+    fn __len__(&self) -> usize {
+        self.insts.len()
+    }
+
+    fn __getitem__(&self, idx: isize) -> PyResult<PyObject> {
+        let index = checked_index(idx, self.insts.len())?;
+        Python::with_gil(|py| self.get(py, index))
+    }
+}
+
+/// Indexing with a slice gives a `Circuit` of those instructions, on the same
+/// qubits.
+#[pyproto]
+impl PyMappingProtocol for Circuit {
+    fn __getitem__(&self, key: &PyAny) -> PyResult<PyObject> {
+        Python::with_gil(|py| match Index::extract(key, self.insts.len())? {
+            Index::Single(index) => self.get(py, index),
+            Index::Slice(indices) => {
+                let insts = indices.map(|i| self.insts[i].clone()).collect();
+                Ok(Py::new(py, self.with_insts(py, insts))?.into_py(py))
+            }
+        })
+    }
+}
+
+#[pyproto]
+impl PyIterProtocol for Circuit {
+    fn __iter__(slf: PyRef<Self>) -> PyResult<Py<CircuitIter>> {
+        let iter = CircuitIter {
+            circuit: slf.into(),
+            index: 0,
+        };
+        Python::with_gil(|py| Py::new(py, iter))
+    }
+}
+
+/// A session's cache holds circuits that refer back to the session.
+#[pyproto]
+impl PyGCProtocol for Circuit {
+    fn __traverse__(&self, visit: PyVisit) -> Result<(), PyTraverseError> {
+        if let Some(session) = &self.session {
+            visit.call(session)?;
+        }
+        if let Some(Ok(interned)) = self.interned.as_ref().map(RefCell::try_borrow) {
+            for obj in interned.values() {
+                visit.call(obj)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn __clear__(&mut self) {
+        self.session = None;
+        if let Some(interned) = &self.interned {
+            interned.borrow_mut().clear();
+        }
+    }
+}
+
+#[pyproto]
+impl PyObjectProtocol for Circuit {
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!(
+            "Circuit([{}])",
+            self.fmt_insts("__repr__")?.join(", ")
+        ))
+    }
+
+    /// One instruction per line
+    fn __str__(&self) -> PyResult<String> {
+        Ok(self.fmt_insts("__str__")?.join("\n"))
+    }
+}
+
+/// An index into a circuit of `len` instructions, counting from the end if
+/// negative
+pub(crate) fn checked_index(idx: isize, len: usize) -> PyResult<usize> {
+    let index = if idx < 0 { idx + len as isize } else { idx };
+    if index < 0 || index >= len as isize {
+        return Err(PyIndexError::new_err("circuit index out of range"));
+    }
+    Ok(index as usize)
+}
+
+/// What a circuit can be indexed by
+pub(crate) enum Index {
+    Single(usize),
+    /// The indices selected by a slice, in its order
+    Slice(Box<dyn Iterator<Item = usize>>),
+}
+
+impl Index {
+    pub(crate) fn extract(key: &PyAny, len: usize) -> PyResult<Self> {
+        if let Ok(slice) = key.downcast::<PySlice>() {
+            let ind = slice.indices(len as _)?;
+            let indices = (0..ind.slicelength).map(move |k| (ind.start + k * ind.step) as usize);
+            return Ok(Self::Slice(Box::new(indices)));
+        }
+        match key.extract::<isize>() {
+            Ok(idx) => Ok(Self::Single(checked_index(idx, len)?)),
+            Err(_) => Err(PyTypeError::new_err(format!(
+                "circuit indices must be integers or slices, not {}",
+                key.get_type().name()?
+            ))),
+        }
+    }
+}
+
+#[pyclass]
+pub(crate) struct CircuitIter {
+    circuit: Py<Circuit>,
+    index: usize,
+}
+
+#[pyproto]
+impl PyIterProtocol for CircuitIter {
+    fn __iter__(slf: PyRef<Self>) -> PyRef<Self> {
+        slf
+    }
+
+    fn __next__(mut slf: PyRefMut<Self>) -> PyResult<Option<PyObject>> {
+        let py = slf.py();
+        let index = slf.index;
+        let circuit = slf.circuit.as_ref(py).borrow();
+        if index >= circuit.insts.len() {
+            return Ok(None);
+        }
+        let obj = circuit.get(py, index)?;
+        drop(circuit);
+        slf.index += 1;
+        Ok(Some(obj))
+    }
+}
+
+/// An iterator over the gates of a compiled circuit that builds each one only
+/// when it's asked for, so that the whole circuit is never held as Python
+/// objects at once.
+#[pyclass]
+pub(crate) struct GateIter {
+    insts: Box<dyn Iterator<Item = Inst> + Send>,
+    /// As in `Circuit`
+    interned: Option<HashMap<Instruction, PyObject>>,
+    mode: MeasMode,
+}
+
+impl GateIter {
+    pub(crate) fn new(circ: CircuitBuf, intern: bool, mode: MeasMode) -> Self {
+        Self {
+            insts: Box::new(circ.into_iter()),
+            interned: if intern { Some(HashMap::new()) } else { None },
+            mode,
+        }
+    }
+
+    /// The Python object for the next instruction, if any
+    pub(crate) fn next_obj(&mut self, py: Python) -> PyResult<Option<PyObject>> {
+        let mode = self.mode;
+        let inst = match self
+            .insts
+            .by_ref()
+            .find_map(|inst| Instruction::from_inst(inst, mode))
+        {
+            Some(inst) => inst,
+            None => return Ok(None),
+        };
+        let interned = match &mut self.interned {
+            Some(interned) => interned,
+            None => return inst.to_py(py).map(Some),
+        };
+        if let Some(obj) = interned.get(&inst) {
+            return Ok(Some(obj.clone_ref(py)));
+        }
+        let obj = inst.to_py(py)?;
+        interned.insert(inst, obj.clone_ref(py));
+        Ok(Some(obj))
+    }
+}
+
+#[pyproto]
+impl PyIterProtocol for GateIter {
+    fn __iter__(slf: PyRef<Self>) -> PyRef<Self> {
+        slf
+    }
+
+    fn __next__(mut slf: PyRefMut<Self>) -> PyResult<Option<PyObject>> {
+        Python::with_gil(|py| slf.next_obj(py))
+    }
+}
@@ -0,0 +1,163 @@
+//! Exports of compiled circuits to other circuit formats
+
+pub(crate) mod provenance;
+pub(crate) mod qasm;
+pub(crate) mod qir;
+pub(crate) mod quil;
+pub(crate) mod schedule;
+pub(crate) mod script;
+pub(crate) mod stim;
+
+use pyo3::{exceptions::PyValueError, prelude::*};
+
+use crate::{circuit::Instruction, gates::GateKind, interop, CavyError};
+
+/// The targets `check_target` knows, each named by its `Circuit` method
+/// without the `to_`
+const TARGETS: &[&str] = &[
+    "qasm2",
+    "qasm3",
+    "qcec",
+    "schedule_json",
+    "experiment_script",
+    "qir",
+    "quil",
+    "stim",
+    "qiskit",
+    "cirq",
+    "tket",
+    "braket",
+    "qulacs",
+];
+
+/// Check a circuit against a target, exactly as its exporter or converter
+/// would before writing anything
+pub(crate) fn check_target(insts: &[Instruction], n_qubits: usize, target: &str) -> PyResult<()> {
+    match target {
+        "qasm2" => qasm::check_qasm2(insts),
+        // Any instruction can be written in OpenQASM 3.
+        "qasm3" => Ok(()),
+        "qcec" => qasm::check_qcec(insts, n_qubits),
+        "schedule_json" => schedule::check(insts),
+        "experiment_script" => script::check(insts),
+        "qir" => qir::check(insts),
+        "quil" => quil::check(insts),
+        "stim" => stim::check(insts),
+        "qiskit" => interop::qiskit::check(insts),
+        "cirq" => interop::cirq::check(insts),
+        "tket" => interop::tket::check(insts),
+        "braket" => interop::braket::check(insts),
+        "qulacs" => interop::qulacs::check(insts),
+        _ => {
+            let msg = format!(
+                "unknown target '{}'; targets are {}",
+                target,
+                TARGETS.join(", ")
+            );
+            Err(PyValueError::new_err(msg))
+        }
+    }
+}
+
+/// A quantum gate in which a CX is treated as a singly-controlled X, so that
+/// exporters only have to handle one way of writing each controlled gate.
+pub(crate) struct FlatGate<'a> {
+    pub(crate) kind: GateKind,
+    pub(crate) ctrls: Vec<usize>,
+    pub(crate) tgts: &'a [usize],
+}
+
+impl<'a> FlatGate<'a> {
+    pub(crate) fn new(kind: GateKind, qbs: &'a [usize], ctrls: &[usize]) -> Self {
+        let mut ctrls = ctrls.to_vec();
+        match kind {
+            GateKind::CX => {
+                ctrls.push(qbs[0]);
+                Self {
+                    kind: GateKind::X,
+                    ctrls,
+                    tgts: &qbs[1..],
+                }
+            }
+            _ => Self {
+                kind,
+                ctrls,
+                tgts: qbs,
+            },
+        }
+    }
+
+    /// The gate's name with a `c` for each control, as in `cx`, `ccx`, and
+    /// `ch`
+    pub(crate) fn name(&self) -> String {
+        "c".repeat(self.ctrls.len()) + self.kind.qasm_name()
+    }
+}
+
+/// The classical bits used by an instruction
+pub(crate) fn cbits(inst: &Instruction) -> Box<dyn Iterator<Item = usize> + '_> {
+    match inst {
+        Instruction::Meas { cb, .. }
+        | Instruction::CInit(cb)
+        | Instruction::CFree { cb, .. }
+        | Instruction::Out { cb, .. } => Box::new(std::iter::once(*cb)),
+        Instruction::CGate { cbs, ctrls, .. } => Box::new(cbs.iter().chain(ctrls).copied()),
+        Instruction::Gate { .. } => Box::new(std::iter::empty()),
+    }
+}
+
+/// The number of classical bits used by a circuit
+pub(crate) fn cbit_count(insts: &[Instruction]) -> usize {
+    insts
+        .iter()
+        .flat_map(cbits)
+        .map(|cb| cb + 1)
+        .max()
+        .unwrap_or(0)
+}
+
+/// Check that a format can represent every instruction before writing any of
+/// them, so that an export fails with a complete list of the offending
+/// instructions rather than partway through.
+pub(crate) fn check_supported(
+    insts: &[Instruction],
+    format: &str,
+    supported: impl Fn(&Instruction) -> bool,
+) -> PyResult<()> {
+    check_all(insts, &format!("export to {}", format), supported)
+}
+
+/// Check that an action can be applied to every instruction, raising an error
+/// listing every one that it can't otherwise
+pub(crate) fn check_all(
+    insts: &[Instruction],
+    action: &str,
+    supported: impl Fn(&Instruction) -> bool,
+) -> PyResult<()> {
+    let unsupported = insts
+        .iter()
+        .enumerate()
+        .filter(|(_, inst)| !supported(inst));
+    reject(action, unsupported)
+}
+
+/// Raise an error listing the instructions, with their indices, that an action
+/// can't be applied to, if there are any
+pub(crate) fn reject<'a>(
+    action: &str,
+    unsupported: impl IntoIterator<Item = (usize, &'a Instruction)>,
+) -> PyResult<()> {
+    let unsupported: Vec<_> = unsupported
+        .into_iter()
+        .map(|(i, inst)| format!("{}: {}", i, inst))
+        .collect();
+    if unsupported.is_empty() {
+        return Ok(());
+    }
+    let msg = format!(
+        "cannot {}; unsupported instructions:\n{}",
+        action,
+        unsupported.join("\n")
+    );
+    Err(PyErr::new::<CavyError, _>(msg))
+}
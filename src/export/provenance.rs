@@ -0,0 +1,66 @@
+//! The comment header of textual exports, recording what produced them
+
+use std::{
+    fmt::Write,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Where a circuit came from, as far as it's known
+pub(crate) struct Provenance {
+    /// The fingerprint of the options of the session that compiled it
+    pub(crate) config: Option<u64>,
+    /// The fingerprint of its full source, prelude included
+    pub(crate) source: Option<u64>,
+}
+
+/// A 64-bit FNV-1a hash, which, unlike the standard library's hashers, is the
+/// same in every build and on every platform. It identifies, and doesn't
+/// protect, what it hashes.
+pub(crate) fn fingerprint(data: &str) -> u64 {
+    data.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// The header, each of whose lines starts with the format's `comment` marker
+pub(crate) fn header(comment: &str, provenance: &Provenance, timestamp: bool) -> String {
+    let mut out = String::new();
+    writeln!(out, "{} pycavy {}", comment, env!("CARGO_PKG_VERSION")).unwrap();
+    if let Some(config) = provenance.config {
+        writeln!(out, "{} config: {:016x}", comment, config).unwrap();
+    }
+    if let Some(source) = provenance.source {
+        writeln!(out, "{} source: {:016x}", comment, source).unwrap();
+    }
+    if timestamp {
+        writeln!(out, "{} generated: {}", comment, utc_now()).unwrap();
+    }
+    out
+}
+
+/// The current time in UTC, in ISO 8601 format, to the second
+fn utc_now() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let (days, secs) = ((secs / 86400) as i64, secs % 86400);
+    // Howard Hinnant's `civil_from_days`, for eras of 400 years from March
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
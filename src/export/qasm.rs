@@ -0,0 +1,230 @@
+//! OpenQASM export
+
+use std::{fmt::Write, io};
+
+use pyo3::prelude::*;
+
+use super::{cbit_count, cbits, check_supported, reject, schedule::Timing, FlatGate};
+use crate::{
+    circuit::Instruction,
+    gates::{CGateKind, GateKind, ReprStyle},
+};
+
+/// The `qelib1.inc` name of a gate, if it has one
+fn qasm2_name(gate: &FlatGate) -> Option<&'static str> {
+    let name = match (gate.kind, gate.ctrls.len()) {
+        (GateKind::X, 0) => "x",
+        (GateKind::X, 1) => "cx",
+        (GateKind::X, 2) => "ccx",
+        (GateKind::Z, 0) => "z",
+        (GateKind::Z, 1) => "cz",
+        (GateKind::H, 0) => "h",
+        (GateKind::H, 1) => "ch",
+        (GateKind::T, 0) => "t",
+        (GateKind::T, 1) => "cu1(pi/4)",
+        (GateKind::TDag, 0) => "tdg",
+        (GateKind::TDag, 1) => "cu1(-pi/4)",
+        (GateKind::SWAP, 0) => "swap",
+        (GateKind::SWAP, 1) => "cswap",
+        _ => return None,
+    };
+    Some(name)
+}
+
+fn qasm2_supported(inst: &Instruction) -> bool {
+    match inst {
+        Instruction::Gate { kind, qbs, ctrls } => {
+            qasm2_name(&FlatGate::new(*kind, qbs, ctrls)).is_some()
+        }
+        Instruction::CGate { .. } => false,
+        _ => true,
+    }
+}
+
+/// Write a gate's operands, controls first
+fn write_args(out: &mut String, gate: &FlatGate) {
+    let args: Vec<_> = gate
+        .ctrls
+        .iter()
+        .chain(gate.tgts)
+        .map(|q| format!("q[{}]", q))
+        .collect();
+    out.push_str(&args.join(","));
+}
+
+pub(crate) fn check_qasm2(insts: &[Instruction]) -> PyResult<()> {
+    check_supported(insts, "OpenQASM 2", qasm2_supported)
+}
+
+pub(crate) fn to_qasm2(insts: &[Instruction], n_qubits: usize) -> PyResult<String> {
+    check_qasm2(insts)?;
+
+    let mut out = qasm2_header(n_qubits, cbit_count(insts));
+    for inst in insts {
+        write_qasm2_inst(&mut out, inst);
+    }
+    Ok(out)
+}
+
+/// Write OpenQASM 2, after a comment `header`, for instructions decoded one at
+/// a time, which are decoded twice: once to check them and count their bits,
+/// and again to write them.
+pub(crate) fn write_qasm2<I>(
+    out: &mut impl io::Write,
+    header: &str,
+    insts: impl Fn() -> I,
+    n_qubits: usize,
+) -> PyResult<()>
+where
+    I: Iterator<Item = PyResult<Instruction>>,
+{
+    let mut unsupported = vec![];
+    let mut n_cbits = 0;
+    for (i, inst) in insts().enumerate() {
+        let inst = inst?;
+        n_cbits = cbits(&inst).map(|cb| cb + 1).fold(n_cbits, usize::max);
+        if !qasm2_supported(&inst) {
+            unsupported.push((i, inst));
+        }
+    }
+    reject(
+        "export to OpenQASM 2",
+        unsupported.iter().map(|(i, inst)| (*i, inst)),
+    )?;
+
+    out.write_all(header.as_bytes())?;
+    out.write_all(qasm2_header(n_qubits, n_cbits).as_bytes())?;
+    let mut line = String::new();
+    for inst in insts() {
+        line.clear();
+        write_qasm2_inst(&mut line, &inst?);
+        out.write_all(line.as_bytes())?;
+    }
+    Ok(())
+}
+
+fn qasm2_header(n_qubits: usize, n_cbits: usize) -> String {
+    let mut out = String::new();
+    out.push_str("OPENQASM 2.0;\ninclude \"qelib1.inc\";\n");
+    if n_qubits > 0 {
+        writeln!(out, "qreg q[{}];", n_qubits).unwrap();
+    }
+    if n_cbits > 0 {
+        writeln!(out, "creg c[{}];", n_cbits).unwrap();
+    }
+    out
+}
+
+/// Write an instruction that `qasm2_supported` has accepted
+fn write_qasm2_inst(out: &mut String, inst: &Instruction) {
+    match inst {
+        Instruction::Gate { kind, qbs, ctrls } => {
+            let gate = FlatGate::new(*kind, qbs, ctrls);
+            out.push_str(qasm2_name(&gate).unwrap());
+            out.push(' ');
+            write_args(out, &gate);
+            out.push_str(";\n");
+        }
+        Instruction::Meas { qb, cb, .. } => {
+            writeln!(out, "measure q[{}] -> c[{}];", qb, cb).unwrap();
+        }
+        // Classical bits are all declared up front, and every bit of a
+        // `creg` is an output.
+        Instruction::CInit(_) | Instruction::CFree { .. } | Instruction::Out { .. } => {}
+        Instruction::CGate { .. } => unreachable!(),
+    }
+}
+
+/// OpenQASM 2 for an equivalence checker, which compares the unitary parts of
+/// circuits, and so only accepts measurements at the end
+pub(crate) fn to_qcec(insts: &[Instruction], n_qubits: usize) -> PyResult<String> {
+    check_final_measurements(insts, n_qubits)?;
+    to_qasm2(insts, n_qubits)
+}
+
+pub(crate) fn check_qcec(insts: &[Instruction], n_qubits: usize) -> PyResult<()> {
+    check_final_measurements(insts, n_qubits)?;
+    check_qasm2(insts)
+}
+
+fn check_final_measurements(insts: &[Instruction], n_qubits: usize) -> PyResult<()> {
+    let mut measured = vec![false; n_qubits];
+    let mut mid_circuit = vec![];
+    for (i, inst) in insts.iter().enumerate().rev() {
+        if let Instruction::Meas { qb, .. } = inst {
+            if measured[*qb] {
+                mid_circuit.push((i, inst));
+            }
+        }
+        for qb in inst.qubits() {
+            measured[qb] = true;
+        }
+    }
+    mid_circuit.reverse();
+    reject(
+        "export to QCEC, which requires final measurements",
+        mid_circuit,
+    )
+}
+
+/// A classical gate as OpenQASM 3 assignments to bits of `c`, in an `if`
+/// statement on its controls, if it has any
+fn qasm3_cgate(kind: CGateKind, cbs: &[usize], ctrls: &[usize]) -> String {
+    let body = match (kind, cbs) {
+        (CGateKind::Not, [a]) => format!("c[{0}] = ~c[{0}];", a),
+        (CGateKind::Copy, [src, dst]) => format!("c[{}] = c[{}];", dst, src),
+        (CGateKind::Swap, [a, b]) => format!(
+            "c[{0}] = c[{0}] ^ c[{1}]; c[{1}] = c[{0}] ^ c[{1}]; c[{0}] = c[{0}] ^ c[{1}];",
+            a, b
+        ),
+        _ => unreachable!(),
+    };
+    if ctrls.is_empty() {
+        return body;
+    }
+    let cond: Vec<_> = ctrls.iter().map(|cb| format!("c[{}]", cb)).collect();
+    format!("if ({}) {{ {} }}", cond.join(" && "), body)
+}
+
+/// OpenQASM 3, in which measurement outcomes can feed classical gates, and
+/// classical controls become `if` statements
+pub(crate) fn to_qasm3(
+    insts: &[Instruction],
+    n_qubits: usize,
+    timing: Option<&Timing>,
+) -> PyResult<String> {
+    let mut out = String::new();
+    out.push_str("OPENQASM 3.0;\ninclude \"stdgates.inc\";\n");
+    if n_qubits > 0 {
+        writeln!(out, "qubit[{}] q;", n_qubits).unwrap();
+    }
+    let n_cbits = cbit_count(insts);
+    if n_cbits > 0 {
+        writeln!(out, "bit[{}] c;", n_cbits).unwrap();
+    }
+
+    for inst in insts {
+        let line = match inst {
+            // Any number of controls can be written with the `ctrl @` modifier
+            Instruction::Gate { kind, qbs, ctrls } => {
+                ReprStyle::Qasm.fmt_gate(kind.name(), kind.qasm_name(), qbs, ctrls)
+            }
+            Instruction::Meas { qb, cb, .. } => format!("c[{}] = measure q[{}];", cb, qb),
+            // Classical gates take no time on the device's schedule
+            Instruction::CGate { kind, cbs, ctrls } => {
+                writeln!(out, "{}", qasm3_cgate(*kind, cbs, ctrls)).unwrap();
+                continue;
+            }
+            Instruction::CInit(_) | Instruction::CFree { .. } | Instruction::Out { .. } => continue,
+        };
+        // Each operation is boxed with its duration, if it has one
+        match timing {
+            Some(timing) => {
+                let duration = timing.duration(inst);
+                writeln!(out, "box[{}{}] {{ {} }}", duration, timing.unit, line).unwrap()
+            }
+            None => writeln!(out, "{}", line).unwrap(),
+        }
+    }
+    Ok(out)
+}
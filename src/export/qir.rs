@@ -0,0 +1,161 @@
+//! QIR export, as textual LLVM IR following the base profile conventions, or
+//! the adaptive profile's for circuits that keep using measured qubits
+
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt::Write,
+};
+
+use pyo3::prelude::*;
+
+use super::{check_supported, FlatGate};
+use crate::{circuit::Instruction, gates::GateKind};
+
+/// The QIS intrinsic implementing a gate, if there is one
+fn intrinsic(gate: &FlatGate) -> Option<&'static str> {
+    let name = match (gate.kind, gate.ctrls.len()) {
+        (GateKind::X, 0) => "x",
+        (GateKind::X, 1) => "cnot",
+        (GateKind::X, 2) => "ccx",
+        (GateKind::Z, 0) => "z",
+        (GateKind::Z, 1) => "cz",
+        (GateKind::H, 0) => "h",
+        (GateKind::T, 0) => "t",
+        (GateKind::TDag, 0) => "t__adj",
+        (GateKind::SWAP, 0) => "swap",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// A statically allocated qubit or result, which QIR addresses by pointer
+fn ptr(ty: &str, index: usize) -> String {
+    if index == 0 {
+        format!("%{}* null", ty)
+    } else {
+        format!("%{0}* inttoptr (i64 {1} to %{0}*)", ty, index)
+    }
+}
+
+/// Check that every gate has an intrinsic
+pub(crate) fn check(insts: &[Instruction]) -> PyResult<()> {
+    check_supported(insts, "QIR", |inst| match inst {
+        Instruction::Gate { kind, qbs, ctrls } => {
+            intrinsic(&FlatGate::new(*kind, qbs, ctrls)).is_some()
+        }
+        Instruction::CGate { .. } => false,
+        _ => true,
+    })
+}
+
+pub(crate) fn to_qir(insts: &[Instruction], n_qubits: usize) -> PyResult<String> {
+    check(insts)?;
+
+    // Only measured bits are results, numbered in the order of the bits
+    let measured: BTreeSet<_> = insts
+        .iter()
+        .filter_map(|inst| match inst {
+            Instruction::Meas { cb, .. } => Some(*cb),
+            _ => None,
+        })
+        .collect();
+    let results: HashMap<_, _> = measured
+        .iter()
+        .enumerate()
+        .map(|(i, &cb)| (cb, i))
+        .collect();
+
+    // The base profile allows nothing to happen to a qubit once it's measured,
+    // and each result to be written only once
+    let mut measured_qubits = HashSet::new();
+    let mut written = HashSet::new();
+    let mut adaptive = false;
+    // Each declaration is a name and its number of operands
+    let mut decls = BTreeSet::new();
+    let mut body = String::new();
+    for inst in insts {
+        if let Instruction::Gate { .. } | Instruction::Meas { .. } = inst {
+            adaptive |= inst.qubits().any(|qb| measured_qubits.contains(&qb));
+        }
+        match inst {
+            Instruction::Gate { kind, qbs, ctrls } => {
+                let gate = FlatGate::new(*kind, qbs, ctrls);
+                let name = format!("__quantum__qis__{}__body", intrinsic(&gate).unwrap());
+                let args: Vec<_> = gate
+                    .ctrls
+                    .iter()
+                    .chain(gate.tgts)
+                    .map(|&q| ptr("Qubit", q))
+                    .collect();
+                writeln!(body, "  call void @{}({})", name, args.join(", ")).unwrap();
+                decls.insert((name, vec!["%Qubit*"; args.len()]));
+            }
+            Instruction::Meas { qb, cb, .. } => {
+                measured_qubits.insert(*qb);
+                adaptive |= !written.insert(*cb);
+                let name = "__quantum__qis__mz__body".to_owned();
+                writeln!(
+                    body,
+                    "  call void @{}({}, {}) #1",
+                    name,
+                    ptr("Qubit", *qb),
+                    ptr("Result", results[cb])
+                )
+                .unwrap();
+                decls.insert((name, vec!["%Qubit*", "%Result* writeonly"]));
+            }
+            Instruction::CInit(_) | Instruction::CFree { .. } | Instruction::Out { .. } => {}
+            Instruction::CGate { .. } => unreachable!(),
+        }
+    }
+
+    // Every result is recorded as an output, at the end, in order
+    let n_results = measured.len();
+    if n_results > 0 {
+        let name = "__quantum__rt__result_record_output".to_owned();
+        for result in 0..n_results {
+            writeln!(
+                body,
+                "  call void @{}({}, i8* null)",
+                name,
+                ptr("Result", result)
+            )
+            .unwrap();
+        }
+        decls.insert((name, vec!["%Result*", "i8*"]));
+    }
+
+    let mut out = String::new();
+    out.push_str("; ModuleID = 'cavy'\nsource_filename = \"cavy\"\n\n");
+    out.push_str("%Qubit = type opaque\n%Result = type opaque\n\n");
+    out.push_str("define void @main() #0 {\nentry:\n");
+    out.push_str(&body);
+    out.push_str("  ret void\n}\n\n");
+    for (name, params) in &decls {
+        writeln!(out, "declare void @{}({})", name, params.join(", ")).unwrap();
+    }
+    let profile = if adaptive {
+        "adaptive_profile"
+    } else {
+        "base_profile"
+    };
+    writeln!(
+        out,
+        "\nattributes #0 = {{ \"entry_point\" \"qir_profiles\"=\"{}\" \
+         \"output_labeling_schema\"=\"schema_id\" \"required_num_qubits\"=\"{}\" \
+         \"required_num_results\"=\"{}\" }}",
+        profile, n_qubits, n_results
+    )
+    .unwrap();
+    out.push_str("attributes #1 = { \"irreversible\" }\n");
+    // Both profiles require these flags: QIR 1.0, with every qubit and result
+    // allocated statically
+    out.push_str(concat!(
+        "\n!llvm.module.flags = !{!0, !1, !2, !3}\n\n",
+        "!0 = !{i32 1, !\"qir_major_version\", i32 1}\n",
+        "!1 = !{i32 7, !\"qir_minor_version\", i32 0}\n",
+        "!2 = !{i32 1, !\"dynamic_qubit_management\", i1 false}\n",
+        "!3 = !{i32 1, !\"dynamic_result_management\", i1 false}\n",
+    ));
+    Ok(out)
+}
@@ -0,0 +1,54 @@
+//! Quil export
+
+use std::fmt::Write;
+
+use pyo3::prelude::*;
+
+use super::{cbit_count, check_supported};
+use crate::{circuit::Instruction, gates::GateKind};
+
+fn quil_name(kind: GateKind) -> &'static str {
+    match kind {
+        GateKind::H => "H",
+        GateKind::Z => "Z",
+        GateKind::X => "X",
+        GateKind::T => "T",
+        GateKind::TDag => "DAGGER T",
+        GateKind::CX => "CNOT",
+        GateKind::SWAP => "SWAP",
+    }
+}
+
+/// Check that every instruction can be written in Quil
+pub(crate) fn check(insts: &[Instruction]) -> PyResult<()> {
+    check_supported(insts, "Quil", |inst| {
+        !matches!(inst, Instruction::CGate { .. })
+    })
+}
+
+pub(crate) fn to_quil(insts: &[Instruction]) -> PyResult<String> {
+    check(insts)?;
+
+    let mut out = String::new();
+    let n_cbits = cbit_count(insts);
+    if n_cbits > 0 {
+        writeln!(out, "DECLARE ro BIT[{}]", n_cbits).unwrap();
+    }
+    for inst in insts {
+        match inst {
+            // Quil can write any number of controls with the `CONTROLLED`
+            // modifier, whose qubits come before the gate's own
+            Instruction::Gate { kind, qbs, ctrls } => {
+                let name = "CONTROLLED ".repeat(ctrls.len()) + quil_name(*kind);
+                let args: Vec<_> = ctrls.iter().chain(qbs).map(usize::to_string).collect();
+                writeln!(out, "{} {}", name, args.join(" ")).unwrap();
+            }
+            Instruction::Meas { qb, cb, .. } => {
+                writeln!(out, "MEASURE {} ro[{}]", qb, cb).unwrap();
+            }
+            Instruction::CInit(_) | Instruction::CFree { .. } | Instruction::Out { .. } => {}
+            Instruction::CGate { .. } => unreachable!(),
+        }
+    }
+    Ok(out)
+}
@@ -0,0 +1,232 @@
+//! Moment scheduling of circuits, and the schedule export for hardware
+//! control systems
+
+use std::collections::HashMap;
+
+use pyo3::{
+    exceptions::PyValueError,
+    prelude::*,
+    types::{PyDict, PyList},
+};
+
+use super::{check_supported, FlatGate};
+use crate::circuit::Instruction;
+
+/// The units that OpenQASM 3 durations can be written in
+const UNITS: &[&str] = &["dt", "ns", "us", "ms", "s"];
+
+/// The name of an operation in a schedule, by which its duration is given.
+/// Controlled gates have a `c` for each control, so that a CX and a Toffoli
+/// can be given different durations.
+pub(crate) fn op_name(inst: &Instruction) -> String {
+    match inst {
+        Instruction::Gate { kind, qbs, ctrls } => FlatGate::new(*kind, qbs, ctrls).name(),
+        Instruction::Meas { .. } => "measure".to_owned(),
+        _ => unreachable!(),
+    }
+}
+
+/// Physical timing attached to a schedule: the duration of each kind of
+/// operation, by its name, in a single time unit
+pub(crate) struct Timing {
+    pub(crate) unit: String,
+    durations: HashMap<String, f64>,
+}
+
+impl Timing {
+    /// Check user-supplied timing against the circuit it's for, which must
+    /// have a duration for every operation it uses; there's no timing if
+    /// neither the unit nor the durations are given.
+    pub(crate) fn new(
+        insts: &[Instruction],
+        unit: Option<String>,
+        durations: Option<HashMap<String, f64>>,
+    ) -> PyResult<Option<Self>> {
+        let (unit, durations) = match (unit, durations) {
+            (None, None) => return Ok(None),
+            (Some(unit), Some(durations)) => (unit, durations),
+            _ => {
+                return Err(PyValueError::new_err(
+                    "a time unit and durations must be given together",
+                ))
+            }
+        };
+        if !UNITS.contains(&unit.as_str()) {
+            let msg = format!(
+                "time unit must be one of {}, not '{}'",
+                UNITS.join(", "),
+                unit
+            );
+            return Err(PyValueError::new_err(msg));
+        }
+        if let Some((op, dur)) = durations
+            .iter()
+            .find(|(_, dur)| !(dur.is_finite() && **dur >= 0.0))
+        {
+            let msg = format!("duration of '{}' must be nonnegative, not {}", op, dur);
+            return Err(PyValueError::new_err(msg));
+        }
+        let mut missing: Vec<_> = insts
+            .iter()
+            .filter(|inst| inst.qubits().next().is_some())
+            .map(op_name)
+            .filter(|op| !durations.contains_key(op))
+            .collect();
+        missing.sort_unstable();
+        missing.dedup();
+        if !missing.is_empty() {
+            let msg = format!("no durations given for {}", missing.join(", "));
+            return Err(PyValueError::new_err(msg));
+        }
+        Ok(Some(Self { unit, durations }))
+    }
+
+    pub(crate) fn duration(&self, inst: &Instruction) -> f64 {
+        self.durations[&op_name(inst)]
+    }
+
+    /// The length of the time unit in seconds, unless it's the device's own
+    /// `dt`
+    pub(crate) fn unit_seconds(&self) -> Option<f64> {
+        match self.unit.as_str() {
+            "ns" => Some(1e-9),
+            "us" => Some(1e-6),
+            "ms" => Some(1e-3),
+            "s" => Some(1.0),
+            _ => None,
+        }
+    }
+}
+
+/// The name of the control channel driving a qubit
+fn channel(qb: usize) -> String {
+    format!("q{}", qb)
+}
+
+/// Schedule the quantum instructions of a circuit as soon as possible: each is
+/// placed in the first moment after every earlier instruction on its qubits.
+/// Classical instructions take no time on any channel and are left out.
+pub(crate) fn moments(insts: &[Instruction], n_qubits: usize) -> Vec<Vec<&Instruction>> {
+    let mut free_at = vec![0; n_qubits];
+    let mut moments: Vec<Vec<&Instruction>> = vec![];
+    for inst in insts {
+        let time = match inst.qubits().map(|q| free_at[q]).max() {
+            Some(time) => time,
+            None => continue,
+        };
+        for q in inst.qubits() {
+            free_at[q] = time + 1;
+        }
+        if moments.len() <= time {
+            moments.resize_with(time + 1, Vec::new);
+        }
+        moments[time].push(inst);
+    }
+    moments
+}
+
+/// The length of the critical path through a circuit: when its last operation
+/// finishes, if each starts as soon as every earlier operation on its qubits
+/// has finished. Unlike a schedule of moments, this doesn't wait for a whole
+/// moment to finish before starting the next.
+pub(crate) fn critical_path(insts: &[Instruction], n_qubits: usize, timing: &Timing) -> f64 {
+    let mut free_at = vec![0.0; n_qubits];
+    for inst in insts {
+        let start = match inst.qubits().map(|q| free_at[q]).reduce(f64::max) {
+            Some(start) => start,
+            None => continue,
+        };
+        let end = start + timing.duration(inst);
+        for q in inst.qubits() {
+            free_at[q] = end;
+        }
+    }
+    free_at.into_iter().fold(0.0, f64::max)
+}
+
+/// The schedule entry for a single operation
+fn op_dict<'p>(
+    py: Python<'p>,
+    inst: &Instruction,
+    timing: Option<&Timing>,
+) -> PyResult<&'p PyDict> {
+    let op = PyDict::new(py);
+    op.set_item("op", op_name(inst))?;
+    match inst {
+        // Every control, including a CX's, is listed as such
+        Instruction::Gate { kind, qbs, ctrls } => {
+            let gate = FlatGate::new(*kind, qbs, ctrls);
+            op.set_item("qubits", gate.tgts)?;
+            op.set_item("ctrls", gate.ctrls)?;
+        }
+        Instruction::Meas { qb, cb, .. } => {
+            op.set_item("qubits", vec![*qb])?;
+            op.set_item("cbit", *cb)?;
+        }
+        _ => unreachable!(),
+    }
+    let channels: Vec<_> = inst.qubits().map(channel).collect();
+    op.set_item("channels", channels)?;
+    if let Some(timing) = timing {
+        op.set_item("duration", timing.duration(inst))?;
+    }
+    Ok(op)
+}
+
+/// Check that the circuit has no classical gates, which could be feedback
+/// between moments, which this format has no way to express
+pub(crate) fn check(insts: &[Instruction]) -> PyResult<()> {
+    check_supported(insts, "a schedule", |inst| {
+        !matches!(inst, Instruction::CGate { .. })
+    })
+}
+
+pub(crate) fn to_schedule_json(
+    py: Python,
+    insts: &[Instruction],
+    n_qubits: usize,
+    timing: Option<&Timing>,
+) -> PyResult<String> {
+    check(insts)?;
+
+    let moments = moments(insts, n_qubits);
+    let moment_list = PyList::empty(py);
+    // With physical timing, each moment lasts as long as its longest operation
+    let mut start = 0.0;
+    for (time, ops) in moments.iter().enumerate() {
+        let moment = PyDict::new(py);
+        moment.set_item("time", time)?;
+        if let Some(timing) = timing {
+            let duration = ops
+                .iter()
+                .map(|inst| timing.duration(inst))
+                .fold(0.0, f64::max);
+            moment.set_item("start", start)?;
+            moment.set_item("duration", duration)?;
+            start += duration;
+        }
+        let ops = ops
+            .iter()
+            .map(|inst| op_dict(py, inst, timing))
+            .collect::<PyResult<Vec<_>>>()?;
+        moment.set_item("ops", ops)?;
+        moment_list.append(moment)?;
+    }
+
+    let schedule = PyDict::new(py);
+    schedule.set_item("version", 1)?;
+    schedule.set_item("n_qubits", n_qubits)?;
+    schedule.set_item("channels", (0..n_qubits).map(channel).collect::<Vec<_>>())?;
+    schedule.set_item("n_moments", moments.len())?;
+    if let Some(timing) = timing {
+        schedule.set_item("unit", &timing.unit)?;
+        schedule.set_item("duration", start)?;
+    }
+    schedule.set_item("moments", moment_list)?;
+
+    let kwargs = PyDict::new(py);
+    kwargs.set_item("indent", 2)?;
+    py.import("json")?
+        .call("dumps", (schedule,), Some(kwargs))?
+        .extract()
+}
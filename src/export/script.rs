@@ -0,0 +1,86 @@
+//! Generation of Python experiment scaffolds from scheduled circuits, for labs
+//! whose control software (ARTIQ, for example) is driven by Python scripts
+
+use std::{collections::BTreeMap, fmt::Write};
+
+use pyo3::prelude::*;
+
+use super::{check_supported, schedule::moments, FlatGate};
+use crate::circuit::Instruction;
+
+/// The scaffold method implementing an operation, and its arguments
+fn method_call(inst: &Instruction) -> (String, Vec<String>, Vec<usize>) {
+    match inst {
+        Instruction::Gate { kind, qbs, ctrls } => {
+            let gate = FlatGate::new(*kind, qbs, ctrls);
+            let name = gate.name();
+            let mut params: Vec<_> = (0..gate.ctrls.len()).map(|i| format!("c{}", i)).collect();
+            params.extend((0..gate.tgts.len()).map(|i| format!("q{}", i)));
+            let args = gate.ctrls.iter().chain(gate.tgts).copied().collect();
+            (name, params, args)
+        }
+        Instruction::Meas { qb, cb, .. } => (
+            "measure".to_owned(),
+            vec!["q".to_owned(), "c".to_owned()],
+            vec![*qb, *cb],
+        ),
+        _ => unreachable!(),
+    }
+}
+
+/// Check that the circuit has no classical gates, which a schedule can't hold
+pub(crate) fn check(insts: &[Instruction]) -> PyResult<()> {
+    check_supported(insts, "an experiment script", |inst| {
+        !matches!(inst, Instruction::CGate { .. })
+    })
+}
+
+pub(crate) fn to_experiment_script(insts: &[Instruction], n_qubits: usize) -> PyResult<String> {
+    check(insts)?;
+    let moments = moments(insts, n_qubits);
+
+    // Only the operations the circuit actually uses get a stub
+    let mut methods = BTreeMap::new();
+    let mut body = String::new();
+    for (time, ops) in moments.iter().enumerate() {
+        writeln!(body, "        # moment {}", time).unwrap();
+        for inst in ops {
+            let (name, params, args) = method_call(inst);
+            let args: Vec<_> = args.iter().map(usize::to_string).collect();
+            writeln!(body, "        self.{}({})", name, args.join(", ")).unwrap();
+            methods.insert(name, params);
+        }
+    }
+    if moments.is_empty() {
+        body.push_str("        pass\n");
+    }
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        "# Experiment scaffold generated by pycavy {}\n",
+        env!("CARGO_PKG_VERSION")
+    )
+    .unwrap();
+    out.push_str("\nclass Experiment:\n");
+    writeln!(
+        out,
+        "    \"\"\"A compiled Cavy program on {} qubits, scheduled in {} moments.\n",
+        n_qubits,
+        moments.len()
+    )
+    .unwrap();
+    out.push_str("    Implement each operation below with the pulses or control calls that\n");
+    out.push_str("    realize it on your hardware; `run` replays the schedule in order.\n");
+    out.push_str("    \"\"\"\n\n");
+    writeln!(out, "    n_qubits = {}\n", n_qubits).unwrap();
+    for (name, params) in &methods {
+        let mut params = params.clone();
+        params.insert(0, "self".to_owned());
+        writeln!(out, "    def {}({}):", name, params.join(", ")).unwrap();
+        out.push_str("        raise NotImplementedError\n\n");
+    }
+    out.push_str("    def run(self):\n");
+    out.push_str(&body);
+    Ok(out)
+}
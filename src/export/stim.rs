@@ -0,0 +1,63 @@
+//! Stim export, for circuits made only of Clifford gates
+
+use std::fmt::Write;
+
+use pyo3::prelude::*;
+
+use super::{check_supported, FlatGate};
+use crate::{circuit::Instruction, gates::GateKind};
+
+/// The Stim name of a gate, if it's a Clifford gate Stim knows
+fn stim_name(gate: &FlatGate) -> Option<&'static str> {
+    let name = match (gate.kind, gate.ctrls.len()) {
+        (GateKind::X, 0) => "X",
+        (GateKind::X, 1) => "CX",
+        (GateKind::Z, 0) => "Z",
+        (GateKind::Z, 1) => "CZ",
+        (GateKind::H, 0) => "H",
+        (GateKind::SWAP, 0) => "SWAP",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// Check that every gate is a Clifford gate that Stim knows
+pub(crate) fn check(insts: &[Instruction]) -> PyResult<()> {
+    check_supported(
+        insts,
+        "Stim, which requires Clifford gates",
+        |inst| match inst {
+            Instruction::Gate { kind, qbs, ctrls } => {
+                stim_name(&FlatGate::new(*kind, qbs, ctrls)).is_some()
+            }
+            Instruction::CGate { .. } => false,
+            _ => true,
+        },
+    )
+}
+
+pub(crate) fn to_stim(insts: &[Instruction]) -> PyResult<String> {
+    check(insts)?;
+
+    let mut out = String::new();
+    for inst in insts {
+        match inst {
+            Instruction::Gate { kind, qbs, ctrls } => {
+                let gate = FlatGate::new(*kind, qbs, ctrls);
+                let args: Vec<_> = gate
+                    .ctrls
+                    .iter()
+                    .chain(gate.tgts)
+                    .map(usize::to_string)
+                    .collect();
+                writeln!(out, "{} {}", stim_name(&gate).unwrap(), args.join(" ")).unwrap();
+            }
+            Instruction::Meas { qb, .. } => {
+                writeln!(out, "M {}", qb).unwrap();
+            }
+            Instruction::CInit(_) | Instruction::CFree { .. } | Instruction::Out { .. } => {}
+            Instruction::CGate { .. } => unreachable!(),
+        }
+    }
+    Ok(out)
+}
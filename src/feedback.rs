@@ -0,0 +1,65 @@
+//! The dependence of a circuit's classical instructions on its measurement
+//! results. In a compiled circuit, only classical instructions can read a
+//! classical bit, so feedback is found by following the results of
+//! measurements through the classical gates that read them.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::{circuit::Instruction, gates::CGateKind};
+
+/// For each instruction that depends on any measurement results, the indices
+/// of the measurements it depends on, directly or through the classical gates
+/// that computed the bits it reads
+pub(crate) fn feedback_graph(insts: &[Instruction]) -> BTreeMap<usize, Vec<usize>> {
+    // The measurements that the current value of each bit depends on
+    let mut bits: HashMap<usize, BTreeSet<usize>> = HashMap::new();
+    let mut graph = BTreeMap::new();
+    for (i, inst) in insts.iter().enumerate() {
+        let deps = match inst {
+            Instruction::Meas { cb, .. } => {
+                bits.insert(*cb, BTreeSet::from([i]));
+                continue;
+            }
+            Instruction::CInit(cb) | Instruction::CFree { cb, .. } => {
+                bits.remove(cb);
+                continue;
+            }
+            Instruction::Gate { .. } => continue,
+            Instruction::Out { cb, .. } => bits.get(cb).cloned().unwrap_or_default(),
+            Instruction::CGate { kind, cbs, ctrls } => {
+                let read = |cbs: &[usize]| -> BTreeSet<usize> {
+                    cbs.iter()
+                        .filter_map(|cb| bits.get(cb))
+                        .flatten()
+                        .copied()
+                        .collect()
+                };
+                let deps: BTreeSet<usize> = read(cbs).union(&read(ctrls)).copied().collect();
+                match (kind, cbs.as_slice()) {
+                    (CGateKind::Swap, &[fst, snd]) if ctrls.is_empty() => {
+                        let fst_deps = bits.remove(&fst);
+                        let snd_deps = bits.remove(&snd);
+                        bits.extend(snd_deps.map(|deps| (fst, deps)));
+                        bits.extend(fst_deps.map(|deps| (snd, deps)));
+                    }
+                    // Otherwise, every bit the gate can write may depend on
+                    // everything it reads, including the old value of a bit
+                    // left alone when a control is off
+                    (CGateKind::Swap, _) => {
+                        for &cb in cbs {
+                            bits.insert(cb, deps.clone());
+                        }
+                    }
+                    (CGateKind::Not, _) | (CGateKind::Copy, _) => {
+                        bits.insert(*cbs.last().unwrap(), deps.clone());
+                    }
+                }
+                deps
+            }
+        };
+        if !deps.is_empty() {
+            graph.insert(i, deps.into_iter().collect());
+        }
+    }
+    graph
+}
@@ -0,0 +1,480 @@
+//! The Python classes representing individual circuit instructions, and how
+//! they're printed.
+
+use std::{
+    convert::TryInto,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use paste::paste;
+use pyo3::{class::basic::PyObjectProtocol, prelude::*, types::PyTuple};
+use serde::{Deserialize, Serialize};
+
+/// How gates are printed by `repr` and `str`, shared by all gate classes
+#[derive(Clone, Copy)]
+pub(crate) enum ReprStyle {
+    /// `CX[0, 1]`, or `X[2] ctrls[0, 1]` with controls; `Meas[0] -> 1`
+    Compact,
+    /// `CXGate(qbs=[0, 1])`, or `XGate(qbs=[2], ctrls=[0, 1])` with controls;
+    /// `MeasGate(qbs=[0], cb=1)`
+    Verbose,
+    /// `cx q[0],q[1];`, or `ctrl(2) @ x q[0],q[1],q[2];` with controls;
+    /// `measure q[0] -> c[1];`
+    Qasm,
+}
+
+static REPR_STYLE: AtomicU8 = AtomicU8::new(ReprStyle::Compact as u8);
+
+impl ReprStyle {
+    pub(crate) fn get() -> Self {
+        match REPR_STYLE.load(Ordering::Relaxed) {
+            1 => Self::Verbose,
+            2 => Self::Qasm,
+            _ => Self::Compact,
+        }
+    }
+
+    pub(crate) fn fmt_gate(
+        self,
+        name: &str,
+        qasm_name: &str,
+        qbs: &[usize],
+        ctrls: &[usize],
+    ) -> String {
+        match self {
+            Self::Compact if ctrls.is_empty() => format!("{}{:?}", name, qbs),
+            Self::Compact => format!("{}{:?} ctrls{:?}", name, qbs, ctrls),
+            Self::Verbose if ctrls.is_empty() => format!("{}Gate(qbs={:?})", name, qbs),
+            Self::Verbose => format!("{}Gate(qbs={:?}, ctrls={:?})", name, qbs, ctrls),
+            Self::Qasm => {
+                let modifier = if ctrls.is_empty() {
+                    String::new()
+                } else {
+                    format!("ctrl({}) @ ", ctrls.len())
+                };
+                let args: Vec<_> = ctrls
+                    .iter()
+                    .chain(qbs)
+                    .map(|q| format!("q[{}]", q))
+                    .collect();
+                format!("{}{} {};", modifier, qasm_name, args.join(","))
+            }
+        }
+    }
+
+    /// Classical instructions and qubit allocations have no QASM 2 equivalent,
+    /// so that style prints them as comments.
+    pub(crate) fn fmt_classical(
+        self,
+        name: &str,
+        class: &str,
+        fields: &[(&str, &dyn PyRepr)],
+    ) -> String {
+        let values: Vec<_> = fields.iter().map(|(_, value)| value.py_repr()).collect();
+        match self {
+            Self::Compact => format!("{}({})", name, values.join(", ")),
+            Self::Verbose => {
+                let fields: Vec<_> = fields
+                    .iter()
+                    .map(|(field, value)| format!("{}={}", field, value.py_repr()))
+                    .collect();
+                format!("{}({})", class, fields.join(", "))
+            }
+            Self::Qasm => format!("// {}({})", name, values.join(", ")),
+        }
+    }
+
+    /// The default, nondemolition mode goes unmentioned
+    pub(crate) fn fmt_meas(self, qb: usize, cb: usize, mode: MeasMode) -> String {
+        let demolition = mode == MeasMode::Demolition;
+        match self {
+            Self::Compact if demolition => format!("Meas[{}] -> {} (demolition)", qb, cb),
+            Self::Compact => format!("Meas[{}] -> {}", qb, cb),
+            Self::Verbose if demolition => {
+                format!("MeasGate(qbs=[{}], cb={}, mode=\"demolition\")", qb, cb)
+            }
+            Self::Verbose => format!("MeasGate(qbs=[{}], cb={})", qb, cb),
+            Self::Qasm => format!("measure q[{}] -> c[{}];", qb, cb),
+        }
+    }
+}
+
+/// Fields of instructions, as they'd be written in Python
+pub(crate) trait PyRepr {
+    fn py_repr(&self) -> String;
+}
+
+impl PyRepr for usize {
+    fn py_repr(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl PyRepr for bool {
+    fn py_repr(&self) -> String {
+        if *self { "True" } else { "False" }.to_owned()
+    }
+}
+
+impl PyRepr for &str {
+    fn py_repr(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
+impl PyRepr for String {
+    fn py_repr(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
+impl PyRepr for Vec<usize> {
+    fn py_repr(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
+/// Set the format used to print gates: one of "compact" (the default, e.g.
+/// `CX[0, 1]`), "verbose" (`CXGate(qbs=[0, 1])`), or "qasm" (`cx q[0],q[1];`).
+#[pyfunction]
+pub(crate) fn set_repr_style(style: &str) -> PyResult<()> {
+    let style = match style {
+        "compact" => ReprStyle::Compact,
+        "verbose" => ReprStyle::Verbose,
+        "qasm" => ReprStyle::Qasm,
+        _ => {
+            let msg = format!("unknown repr style '{}'", style);
+            return Err(pyo3::exceptions::PyValueError::new_err(msg));
+        }
+    };
+    REPR_STYLE.store(style as u8, Ordering::Relaxed);
+    Ok(())
+}
+
+#[pyclass(subclass)]
+pub(crate) struct Gate {}
+
+#[pymethods]
+impl Gate {
+    #[new]
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+macro_rules! gates {
+    ($module:ident < $($name:ident[$qbs:expr] $qasm:literal),*) => {
+        /// The kinds of quantum gates, each of which has its own Python class
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+        pub(crate) enum GateKind {
+            $($name),*
+        }
+
+        paste! {
+            impl GateKind {
+                pub(crate) fn name(self) -> &'static str {
+                    match self {
+                        $(Self::$name => stringify!($name)),*
+                    }
+                }
+
+                pub(crate) fn qasm_name(self) -> &'static str {
+                    match self {
+                        $(Self::$name => $qasm),*
+                    }
+                }
+
+                pub(crate) fn from_name(name: &str) -> Option<Self> {
+                    match name {
+                        $(stringify!($name) => Some(Self::$name),)*
+                        _ => None,
+                    }
+                }
+
+                /// The number of qubits the gate acts on, not counting controls
+                pub(crate) fn arity(self) -> usize {
+                    match self {
+                        $(Self::$name => $qbs),*
+                    }
+                }
+
+                /// Build the Python object for a gate of this kind
+                pub(crate) fn pyobj(
+                    self,
+                    py: Python,
+                    qbs: &[usize],
+                    ctrls: &[usize],
+                ) -> PyResult<PyObject> {
+                    match self {
+                        $(Self::$name => {
+                            let qbs = qbs.try_into().expect("wrong number of qubits for gate");
+                            let gate = [<$name Gate>]::new(qbs, Some(ctrls.to_vec()));
+                            Ok(PyCell::new(py, gate)?.to_object(py))
+                        }),*
+                    }
+                }
+            }
+        }
+
+        $(
+
+        paste! {
+            #[pyclass(extends=Gate, subclass)]
+            /// A quantum gate implementing the named operation
+            pub(crate) struct [<$name Gate>] {
+                // Could consider adding a `set` to this
+                #[pyo3(get)]
+                qbs: [usize; $qbs],
+                /// Qubits controlling the gate, if any
+                ctrls: Vec<usize>,
+            }
+
+            #[pymethods]
+            impl [<$name Gate>] {
+                #[new]
+                #[args(ctrls = "None")]
+                fn new(qbs: [usize; $qbs], ctrls: Option<Vec<usize>>) -> (Self, Gate) {
+                    let ctrls = ctrls.unwrap_or_default();
+                    (Self { qbs, ctrls }, Gate::new())
+                }
+
+                #[getter]
+                fn ctrls<'p>(&self, py: Python<'p>) -> &'p PyTuple {
+                    PyTuple::new(py, &self.ctrls)
+                }
+            }
+
+            #[pyproto]
+            impl PyObjectProtocol for [<$name Gate>] {
+                fn __repr__(&self) -> PyResult<String> {
+                    Ok(ReprStyle::get().fmt_gate(
+                        stringify!($name),
+                        $qasm,
+                        &self.qbs,
+                        &self.ctrls,
+                    ))
+                }
+
+                fn __str__(&self) -> PyResult<String> {
+                    self.__repr__()
+                }
+            }
+        }
+        )*
+    };
+}
+
+gates! { m <
+    H[1] "h", Z[1] "z", X[1] "x", T[1] "t", TDag[1] "tdg", CX[2] "cx", SWAP[2] "swap"
+}
+
+/// Whether measuring a qubit consumes it, as set by the session's `meas_mode`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum MeasMode {
+    #[default]
+    Nondemolition,
+    Demolition,
+}
+
+impl MeasMode {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Self::Nondemolition => "nondemolition",
+            Self::Demolition => "demolition",
+        }
+    }
+
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "nondemolition" => Some(Self::Nondemolition),
+            "demolition" => Some(Self::Demolition),
+            _ => None,
+        }
+    }
+}
+
+#[pyclass(extends=Gate, subclass)]
+/// A measurement of a qubit, whose outcome is written to a classical bit
+pub(crate) struct MeasGate {
+    #[pyo3(get)]
+    qbs: [usize; 1],
+    /// The classical bit receiving the measurement outcome
+    #[pyo3(get)]
+    cb: usize,
+    mode: MeasMode,
+}
+
+impl MeasGate {
+    pub(crate) fn pyobj(py: Python, qb: usize, cb: usize, mode: MeasMode) -> PyResult<PyObject> {
+        let gate = Self {
+            qbs: [qb],
+            cb,
+            mode,
+        };
+        Ok(PyCell::new(py, (gate, Gate::new()))?.to_object(py))
+    }
+}
+
+#[pymethods]
+impl MeasGate {
+    #[new]
+    #[args(mode = "\"nondemolition\"")]
+    fn new(qbs: [usize; 1], cb: usize, mode: &str) -> PyResult<(Self, Gate)> {
+        let mode = MeasMode::from_name(mode).ok_or_else(|| {
+            let msg = format!("unknown measurement mode '{}'", mode);
+            pyo3::exceptions::PyValueError::new_err(msg)
+        })?;
+        Ok((Self { qbs, cb, mode }, Gate::new()))
+    }
+
+    /// `"demolition"` or `"nondemolition"`, depending on the `meas_mode` of the
+    /// session that compiled the measurement
+    #[getter]
+    fn mode(&self) -> &'static str {
+        self.mode.name()
+    }
+
+    /// Whether the qubit can still be acted on after the measurement, which it
+    /// can't if a demolition measurement consumed it
+    #[getter]
+    fn qubit_live(&self) -> bool {
+        self.mode == MeasMode::Nondemolition
+    }
+}
+
+#[pyproto]
+impl PyObjectProtocol for MeasGate {
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(ReprStyle::get().fmt_meas(self.qbs[0], self.cb, self.mode))
+    }
+
+    fn __str__(&self) -> PyResult<String> {
+        self.__repr__()
+    }
+}
+
+/// The kinds of classical gates, which the Python class `CGate` names by
+/// their lowercase names
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum CGateKind {
+    /// Negate a bit
+    Not,
+    /// Copy the first bit into the second
+    Copy,
+    /// Exchange two bits
+    Swap,
+}
+
+impl CGateKind {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Self::Not => "not",
+            Self::Copy => "copy",
+            Self::Swap => "swap",
+        }
+    }
+
+    /// The number of bits the gate acts on, not counting controls
+    pub(crate) fn arity(self) -> usize {
+        match self {
+            Self::Not => 1,
+            Self::Copy | Self::Swap => 2,
+        }
+    }
+}
+
+/// The base class of instructions that act only on classical bits
+#[pyclass(subclass)]
+pub(crate) struct ClassicalInst {}
+
+#[pymethods]
+impl ClassicalInst {
+    #[new]
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+/// The base class of qubit allocations and frees, which only
+/// `Circuit.instructions` includes
+#[pyclass(subclass)]
+pub(crate) struct AllocInst {}
+
+#[pymethods]
+impl AllocInst {
+    #[new]
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+/// Classes for instructions other than gates, under the base class given first
+macro_rules! insts {
+    ($base:ident: $($(#[$meta:meta])* $class:ident $name:literal { $($field:ident: $ty:ty),* }),*) => {
+        $(
+
+        $(#[$meta])*
+        #[pyclass(extends=$base, subclass)]
+        pub(crate) struct $class {
+            $(
+            #[pyo3(get)]
+            $field: $ty,
+            )*
+        }
+
+        impl $class {
+            pub(crate) fn pyobj(py: Python, $($field: $ty),*) -> PyResult<PyObject> {
+                Ok(PyCell::new(py, Self::new($($field),*))?.to_object(py))
+            }
+        }
+
+        #[pymethods]
+        impl $class {
+            #[new]
+            fn new($($field: $ty),*) -> (Self, $base) {
+                (Self { $($field),* }, $base::new())
+            }
+        }
+
+        #[pyproto]
+        impl PyObjectProtocol for $class {
+            fn __repr__(&self) -> PyResult<String> {
+                Ok(ReprStyle::get().fmt_classical(
+                    $name,
+                    stringify!($class),
+                    &[$((stringify!($field), &self.$field)),*],
+                ))
+            }
+
+            fn __str__(&self) -> PyResult<String> {
+                self.__repr__()
+            }
+        }
+        )*
+    };
+}
+
+insts! { ClassicalInst:
+    /// The allocation of a classical bit
+    CInitGate "CInit" { cb: usize },
+    /// The release of a classical bit, with the compiler's flag on it, which
+    /// the bindings pass through as is
+    CFreeGate "CFree" { cb: usize, flag: bool },
+    /// A classical gate of the `kind` `"not"`, `"copy"` (from the first bit to
+    /// the second), or `"swap"`, acting on the bits `cbs` if every bit in
+    /// `ctrls` is set
+    CGate "CGate" { kind: String, cbs: Vec<usize>, ctrls: Vec<usize> },
+    /// An output of the program: the bit `cb`, as element `elem` of the output
+    /// called `name`
+    OutGate "Out" { cb: usize, name: String, elem: usize }
+}
+
+insts! { AllocInst:
+    /// The allocation of a qubit
+    QInitGate "QInit" { qb: usize },
+    /// The release of a qubit, with the compiler's flag on it, which the
+    /// bindings pass through as is
+    QFreeGate "QFree" { qb: usize, flag: bool }
+}
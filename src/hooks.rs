@@ -0,0 +1,117 @@
+//! Callbacks that embedders register on a session to observe, or refuse,
+//! each compilation: for quotas, rate limits, and audit logs.
+
+use std::{sync::Mutex, time::Duration};
+
+use pyo3::{prelude::*, types::PyDict, PyTraverseError, PyVisit};
+
+use crate::circuit::Circuit;
+
+#[derive(Default)]
+pub(crate) struct Hooks {
+    before: Mutex<Vec<PyObject>>,
+    after: Mutex<Vec<PyObject>>,
+}
+
+/// How a compilation turned out, as reported to the `after` hooks
+pub(crate) struct Outcome {
+    pub(crate) elapsed: Duration,
+    /// The compiled circuit, or `None`
+    pub(crate) circuit: PyObject,
+    /// The exception raised, or `None`
+    pub(crate) error: PyObject,
+}
+
+impl Outcome {
+    /// The outcome of a compile method that returned `result`
+    pub(crate) fn of(
+        py: Python,
+        elapsed: Duration,
+        result: &PyResult<Option<Py<Circuit>>>,
+    ) -> Self {
+        Self {
+            elapsed,
+            circuit: result.as_ref().ok().cloned().flatten().into_py(py),
+            error: result
+                .as_ref()
+                .err()
+                .map(|err| err.instance(py))
+                .into_py(py),
+        }
+    }
+}
+
+/// What's known about a program before it's compiled
+fn info<'p>(py: Python<'p>, src: &str) -> PyResult<&'p PyDict> {
+    let info = PyDict::new(py);
+    info.set_item("source", src)?;
+    info.set_item("size", src.len())?;
+    info.set_item("lines", src.lines().count())?;
+    Ok(info)
+}
+
+/// Copy out the registered hooks, so that a hook can itself register more
+/// without deadlocking
+fn registered(py: Python, hooks: &Mutex<Vec<PyObject>>) -> Vec<PyObject> {
+    let hooks = hooks.lock().unwrap();
+    hooks.iter().map(|hook| hook.clone_ref(py)).collect()
+}
+
+impl Hooks {
+    pub(crate) fn add(&self, before: Option<PyObject>, after: Option<PyObject>) {
+        if let Some(hook) = before {
+            self.before.lock().unwrap().push(hook);
+        }
+        if let Some(hook) = after {
+            self.after.lock().unwrap().push(hook);
+        }
+    }
+
+    /// Visit the hooks for the garbage collector, since they may well refer to
+    /// the session they're registered on
+    pub(crate) fn traverse(&self, visit: &PyVisit) -> Result<(), PyTraverseError> {
+        for hooks in &[&self.before, &self.after] {
+            if let Ok(hooks) = hooks.try_lock() {
+                for hook in hooks.iter() {
+                    visit.call(hook)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn clear(&self) {
+        self.before.lock().unwrap().clear();
+        self.after.lock().unwrap().clear();
+    }
+
+    /// Run the `before` hooks, in order of registration; an exception raised
+    /// by any of them refuses the compilation.
+    pub(crate) fn before(&self, py: Python, src: &str) -> PyResult<()> {
+        for hook in registered(py, &self.before) {
+            hook.call1(py, (info(py, src)?,))?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn after(&self, py: Python, src: &str, outcome: Outcome) -> PyResult<()> {
+        let hooks = registered(py, &self.after);
+        if hooks.is_empty() {
+            return Ok(());
+        }
+        let info = info(py, src)?;
+        info.set_item("elapsed", outcome.elapsed.as_secs_f64())?;
+        let (n_qubits, instructions) = match outcome.circuit.extract::<PyRef<Circuit>>(py) {
+            Ok(circ) => (Some(circ.n_qubits()), Some(circ.len())),
+            Err(_) => (None, None),
+        };
+        info.set_item("n_qubits", n_qubits)?;
+        info.set_item("instructions", instructions)?;
+        info.set_item("circuit", outcome.circuit)?;
+        info.set_item("error", outcome.error)?;
+        for hook in hooks {
+            hook.call1(py, (info.copy()?,))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,74 @@
+//! Conversion to the Amazon Braket SDK
+
+use std::collections::HashSet;
+
+use pyo3::{
+    prelude::*,
+    types::{PyDict, PyTuple},
+};
+
+use super::import;
+use crate::{
+    circuit::Instruction,
+    export::{reject, FlatGate},
+    gates::GateKind,
+};
+
+/// The `braket.circuits.Circuit` method adding an uncontrolled gate
+fn gate_method(kind: GateKind) -> &'static str {
+    match kind {
+        GateKind::H => "h",
+        GateKind::Z => "z",
+        GateKind::X => "x",
+        GateKind::T => "t",
+        GateKind::TDag => "ti",
+        GateKind::CX => "cnot",
+        GateKind::SWAP => "swap",
+    }
+}
+
+/// Check that every instruction can be converted, before converting any.
+/// Braket circuits measure each qubit at most once, at the end, so nothing
+/// may act on a qubit after it's measured, including another measurement.
+pub(crate) fn check(insts: &[Instruction]) -> PyResult<()> {
+    let mut measured = HashSet::new();
+    let unsupported = insts.iter().enumerate().filter(|(_, inst)| {
+        let after_meas = inst.qubits().any(|qb| measured.contains(&qb));
+        if let Instruction::Meas { qb, .. } = inst {
+            measured.insert(*qb);
+        }
+        after_meas || matches!(inst, Instruction::CGate { .. })
+    });
+    reject("export to Braket", unsupported)
+}
+
+pub(crate) fn to_braket(py: Python, insts: &[Instruction]) -> PyResult<PyObject> {
+    check(insts)?;
+
+    let braket = import(py, "braket.circuits", "amazon-braket-sdk", "to_braket")?;
+    let circ = braket.call0("Circuit")?;
+    for inst in insts {
+        match inst {
+            Instruction::Gate { kind, qbs, ctrls } if ctrls.is_empty() => {
+                circ.call_method1(gate_method(*kind), PyTuple::new(py, qbs))?;
+            }
+            // Other controlled gates are added with the `control` keyword
+            Instruction::Gate { kind, qbs, ctrls } => {
+                let gate = FlatGate::new(*kind, qbs, ctrls);
+                let kwargs = PyDict::new(py);
+                kwargs.set_item("control", gate.ctrls)?;
+                circ.call_method(
+                    gate_method(gate.kind),
+                    PyTuple::new(py, gate.tgts),
+                    Some(kwargs),
+                )?;
+            }
+            Instruction::Meas { qb, .. } => {
+                circ.call_method1("measure", (*qb,))?;
+            }
+            Instruction::CInit(_) | Instruction::CFree { .. } | Instruction::Out { .. } => {}
+            Instruction::CGate { .. } => unreachable!(),
+        }
+    }
+    Ok(circ.into())
+}
@@ -0,0 +1,72 @@
+//! Conversion to Cirq
+
+use pyo3::{
+    prelude::*,
+    types::{PyDict, PyTuple},
+};
+
+use super::import;
+use crate::{
+    circuit::Instruction,
+    export::{check_supported, FlatGate},
+    gates::GateKind,
+};
+
+/// The Cirq gate for an uncontrolled gate
+fn gate(cirq: &PyModule, kind: GateKind) -> PyResult<&PyAny> {
+    match kind {
+        GateKind::H => cirq.getattr("H"),
+        GateKind::Z => cirq.getattr("Z"),
+        GateKind::X => cirq.getattr("X"),
+        GateKind::T => cirq.getattr("T"),
+        GateKind::TDag => cirq.getattr("T")?.call_method1("__pow__", (-1,)),
+        GateKind::CX => cirq.getattr("CX"),
+        GateKind::SWAP => cirq.getattr("SWAP"),
+    }
+}
+
+/// Check that every instruction can be converted, before converting any
+pub(crate) fn check(insts: &[Instruction]) -> PyResult<()> {
+    check_supported(insts, "Cirq", |inst| {
+        !matches!(inst, Instruction::CGate { .. })
+    })
+}
+
+pub(crate) fn to_cirq(py: Python, insts: &[Instruction], n_qubits: usize) -> PyResult<PyObject> {
+    check(insts)?;
+
+    let cirq = import(py, "cirq", "cirq", "to_cirq")?;
+    let qubits: Vec<&PyAny> = cirq
+        .getattr("LineQubit")?
+        .call_method1("range", (n_qubits,))?
+        .extract()?;
+    let mut ops: Vec<&PyAny> = vec![];
+    for inst in insts {
+        match inst {
+            // Controls are added by `controlled`, and their qubits come before
+            // the gate's own
+            Instruction::Gate { kind, qbs, ctrls } => {
+                let flat = FlatGate::new(*kind, qbs, ctrls);
+                let mut op = gate(cirq, flat.kind)?;
+                if !flat.ctrls.is_empty() {
+                    op = op.call_method1("controlled", (flat.ctrls.len(),))?;
+                }
+                let args: Vec<_> = flat
+                    .ctrls
+                    .iter()
+                    .chain(flat.tgts)
+                    .map(|&q| qubits[q])
+                    .collect();
+                ops.push(op.call_method1("on", PyTuple::new(py, args))?);
+            }
+            Instruction::Meas { qb, cb, .. } => {
+                let kwargs = PyDict::new(py);
+                kwargs.set_item("key", format!("c{}", cb))?;
+                ops.push(cirq.call("measure", (qubits[*qb],), Some(kwargs))?);
+            }
+            Instruction::CInit(_) | Instruction::CFree { .. } | Instruction::Out { .. } => {}
+            Instruction::CGate { .. } => unreachable!(),
+        }
+    }
+    Ok(cirq.call1("Circuit", (ops,))?.into())
+}
@@ -0,0 +1,55 @@
+//! Replaying circuits onto any object with a method for each operation, such
+//! as a simulator written in Python or a mock backend
+
+use pyo3::{
+    exceptions::PyTypeError,
+    prelude::*,
+    types::{PyDict, PyTuple},
+};
+
+use crate::circuit::Instruction;
+
+/// The engine method for an instruction, if it has one
+fn method(inst: &Instruction) -> Option<&'static str> {
+    match inst {
+        Instruction::Gate { kind, .. } => Some(kind.qasm_name()),
+        Instruction::Meas { .. } => Some("measure"),
+        _ => None,
+    }
+}
+
+pub(crate) fn apply_to(py: Python, insts: &[Instruction], engine: &PyAny) -> PyResult<()> {
+    // Check for every method before calling any, so that an engine missing
+    // one isn't left with half a circuit applied
+    let mut missing = vec![];
+    for name in insts.iter().filter_map(method) {
+        if !missing.contains(&name) && !engine.hasattr(name)? {
+            missing.push(name);
+        }
+    }
+    if !missing.is_empty() {
+        let msg = format!("engine is missing the methods {}", missing.join(", "));
+        return Err(PyTypeError::new_err(msg));
+    }
+
+    for inst in insts {
+        match inst {
+            Instruction::Gate { kind, qbs, ctrls } => {
+                let kwargs = if ctrls.is_empty() {
+                    None
+                } else {
+                    let kwargs = PyDict::new(py);
+                    kwargs.set_item("ctrls", ctrls.clone())?;
+                    Some(kwargs)
+                };
+                let args = PyTuple::new(py, qbs);
+                engine.call_method(kind.qasm_name(), args, kwargs)?;
+            }
+            Instruction::Meas { qb, .. } => {
+                engine.call_method1("measure", (*qb,))?;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,34 @@
+//! Conversions of compiled circuits into the circuit objects of other Python
+//! quantum computing frameworks. None of these frameworks are dependencies of
+//! pycavy; each is imported only when its conversion is called.
+
+pub(crate) mod braket;
+pub(crate) mod cirq;
+pub(crate) mod engine;
+pub(crate) mod qiskit;
+pub(crate) mod qulacs;
+pub(crate) mod tket;
+
+use pyo3::{exceptions::PyImportError, prelude::*};
+
+/// Import a framework's module, or explain which package a conversion needs if
+/// it isn't installed.
+pub(crate) fn import<'py>(
+    py: Python<'py>,
+    module: &str,
+    package: &str,
+    method: &str,
+) -> PyResult<&'py PyModule> {
+    py.import(module).map_err(|err| {
+        if err.is_instance::<PyImportError>(py) {
+            PyImportError::new_err(format!(
+                "Circuit.{}() requires the '{}' package: {}",
+                method,
+                package,
+                err.pvalue(py)
+            ))
+        } else {
+            err
+        }
+    })
+}
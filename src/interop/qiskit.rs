@@ -0,0 +1,59 @@
+//! Conversion to Qiskit
+
+use pyo3::prelude::*;
+
+use super::import;
+use crate::{
+    circuit::Instruction,
+    export::{cbit_count, check_supported, FlatGate},
+    gates::GateKind,
+};
+
+/// The `qiskit.circuit.library` class of an uncontrolled gate
+fn gate_class(kind: GateKind) -> &'static str {
+    match kind {
+        GateKind::H => "HGate",
+        GateKind::Z => "ZGate",
+        GateKind::X => "XGate",
+        GateKind::T => "TGate",
+        GateKind::TDag => "TdgGate",
+        GateKind::CX => "CXGate",
+        GateKind::SWAP => "SwapGate",
+    }
+}
+
+/// Check that every instruction can be converted, before converting any
+pub(crate) fn check(insts: &[Instruction]) -> PyResult<()> {
+    check_supported(insts, "Qiskit", |inst| {
+        !matches!(inst, Instruction::CGate { .. })
+    })
+}
+
+pub(crate) fn to_qiskit(py: Python, insts: &[Instruction], n_qubits: usize) -> PyResult<PyObject> {
+    check(insts)?;
+
+    let qiskit = import(py, "qiskit", "qiskit", "to_qiskit")?;
+    let library = import(py, "qiskit.circuit.library", "qiskit", "to_qiskit")?;
+    let circ = qiskit.call1("QuantumCircuit", (n_qubits, cbit_count(insts)))?;
+    for inst in insts {
+        match inst {
+            // Every gate is appended as a library gate, with any controls
+            // added by `control`, whose qubits come before the gate's own
+            Instruction::Gate { kind, qbs, ctrls } => {
+                let gate = FlatGate::new(*kind, qbs, ctrls);
+                let mut op = library.call0(gate_class(gate.kind))?;
+                if !gate.ctrls.is_empty() {
+                    op = op.call_method1("control", (gate.ctrls.len(),))?;
+                }
+                let qargs: Vec<_> = gate.ctrls.iter().chain(gate.tgts).copied().collect();
+                circ.call_method1("append", (op, qargs))?;
+            }
+            Instruction::Meas { qb, cb, .. } => {
+                circ.call_method1("measure", (*qb, *cb))?;
+            }
+            Instruction::CInit(_) | Instruction::CFree { .. } | Instruction::Out { .. } => {}
+            Instruction::CGate { .. } => unreachable!(),
+        }
+    }
+    Ok(circ.into())
+}
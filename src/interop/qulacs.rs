@@ -0,0 +1,65 @@
+//! Conversion to Qulacs
+
+use pyo3::{prelude::*, types::PyTuple};
+
+use super::import;
+use crate::{
+    circuit::Instruction,
+    export::{check_supported, FlatGate},
+    gates::GateKind,
+};
+
+/// The `qulacs.gate` function making an uncontrolled gate
+fn gate_fn(kind: GateKind) -> &'static str {
+    match kind {
+        GateKind::H => "H",
+        GateKind::Z => "Z",
+        GateKind::X => "X",
+        GateKind::T => "T",
+        GateKind::TDag => "Tdag",
+        GateKind::CX => "CNOT",
+        GateKind::SWAP => "SWAP",
+    }
+}
+
+/// Check that every instruction can be converted, before converting any
+pub(crate) fn check(insts: &[Instruction]) -> PyResult<()> {
+    check_supported(insts, "Qulacs", |inst| {
+        !matches!(inst, Instruction::CGate { .. })
+    })
+}
+
+pub(crate) fn to_qulacs(py: Python, insts: &[Instruction], n_qubits: usize) -> PyResult<PyObject> {
+    check(insts)?;
+
+    let qulacs = import(py, "qulacs", "qulacs", "to_qulacs")?;
+    let gates = qulacs.getattr("gate")?;
+    let circ = qulacs.call1("QuantumCircuit", (n_qubits,))?;
+    for inst in insts {
+        match inst {
+            Instruction::Gate { kind, qbs, ctrls } if ctrls.is_empty() => {
+                let gate = gates.call_method1(gate_fn(*kind), PyTuple::new(py, qbs))?;
+                circ.call_method1("add_gate", (gate,))?;
+            }
+            // Other controlled gates become matrix gates, each of whose
+            // controls is added to be active on 1
+            Instruction::Gate { kind, qbs, ctrls } => {
+                let flat = FlatGate::new(*kind, qbs, ctrls);
+                let gate = gates.call_method1(gate_fn(flat.kind), PyTuple::new(py, flat.tgts))?;
+                let gate = gates.call_method1("to_matrix_gate", (gate,))?;
+                for &ctrl in &flat.ctrls {
+                    gate.call_method1("add_control_qubit", (ctrl, 1))?;
+                }
+                circ.call_method1("add_gate", (gate,))?;
+            }
+            // Each outcome is stored in the classical register at its bit
+            Instruction::Meas { qb, cb, .. } => {
+                let gate = gates.call_method1("Measurement", (*qb, *cb))?;
+                circ.call_method1("add_gate", (gate,))?;
+            }
+            Instruction::CInit(_) | Instruction::CFree { .. } | Instruction::Out { .. } => {}
+            Instruction::CGate { .. } => unreachable!(),
+        }
+    }
+    Ok(circ.into())
+}
@@ -0,0 +1,63 @@
+//! Conversion to pytket
+
+use pyo3::prelude::*;
+
+use super::import;
+use crate::{
+    circuit::Instruction,
+    export::{cbit_count, check_supported, FlatGate},
+    gates::GateKind,
+};
+
+/// The `OpType` of an uncontrolled gate
+fn op_type(kind: GateKind) -> &'static str {
+    match kind {
+        GateKind::H => "H",
+        GateKind::Z => "Z",
+        GateKind::X => "X",
+        GateKind::T => "T",
+        GateKind::TDag => "Tdg",
+        GateKind::CX => "CX",
+        GateKind::SWAP => "SWAP",
+    }
+}
+
+/// Check that every instruction can be converted, before converting any
+pub(crate) fn check(insts: &[Instruction]) -> PyResult<()> {
+    check_supported(insts, "pytket", |inst| {
+        !matches!(inst, Instruction::CGate { .. })
+    })
+}
+
+pub(crate) fn to_tket(py: Python, insts: &[Instruction], n_qubits: usize) -> PyResult<PyObject> {
+    check(insts)?;
+
+    let tket = import(py, "pytket.circuit", "pytket", "to_tket")?;
+    let circ = tket.call1("Circuit", (n_qubits, cbit_count(insts)))?;
+    let op_types = tket.getattr("OpType")?;
+    for inst in insts {
+        match inst {
+            Instruction::Gate { kind, qbs, ctrls } if ctrls.is_empty() => {
+                let op_type = op_types.getattr(op_type(*kind))?;
+                circ.call_method1("add_gate", (op_type, qbs.clone()))?;
+            }
+            // Other controlled gates become a `QControlBox`, whose control
+            // qubits come before the gate's own
+            Instruction::Gate { kind, qbs, ctrls } => {
+                let gate = FlatGate::new(*kind, qbs, ctrls);
+                let op = tket
+                    .getattr("Op")?
+                    .call_method1("create", (op_types.getattr(op_type(gate.kind))?,))?;
+                let cbox = tket.call1("QControlBox", (op, gate.ctrls.len()))?;
+                let args: Vec<_> = gate.ctrls.iter().chain(gate.tgts).copied().collect();
+                circ.call_method1("add_qcontrolbox", (cbox, args))?;
+            }
+            Instruction::Meas { qb, cb, .. } => {
+                circ.call_method1("Measure", (*qb, *cb))?;
+            }
+            Instruction::CInit(_) | Instruction::CFree { .. } | Instruction::Out { .. } => {}
+            Instruction::CGate { .. } => unreachable!(),
+        }
+    }
+    Ok(circ.into())
+}
@@ -0,0 +1,169 @@
+//! Compiling in a child process forked for each compilation, so that a
+//! compiler that crashes takes only the child down with it, and not the
+//! interpreter.
+//!
+//! The child never touches Python: it compiles on the thread that forked it,
+//! which holds the GIL throughout, transcribes the circuit, writes its binary
+//! encoding to a pipe, and exits without running any destructors or `atexit`
+//! handlers. Unlike a compilation on a thread of this process, one in a child
+//! process can be stopped, so a timed-out or interrupted isolated compilation
+//! kills its child rather than leaving it running.
+
+use std::time::Duration;
+
+use pyo3::prelude::*;
+
+use cavy::session::Config;
+
+use crate::{circuit::Transcript, gates::MeasMode};
+
+/// Compile source in a child process, returning its transcribed circuit, if
+/// any
+#[cfg(unix)]
+pub(crate) fn compile(
+    py: Python,
+    conf: &Config,
+    mode: MeasMode,
+    src: String,
+    timeout: Option<Duration>,
+) -> PyResult<Option<Transcript>> {
+    unix::compile(py, conf, mode, src, timeout)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn compile(
+    _py: Python,
+    _conf: &Config,
+    _mode: MeasMode,
+    _src: String,
+    _timeout: Option<Duration>,
+) -> PyResult<Option<Transcript>> {
+    Err(pyo3::exceptions::PyNotImplementedError::new_err(
+        "isolated compilation needs fork(), which this platform doesn't have",
+    ))
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::{
+        fs::File,
+        io::{self, Read, Write},
+        os::unix::io::FromRawFd,
+        panic::{self, AssertUnwindSafe},
+        sync::atomic::AtomicBool,
+        time::Duration,
+    };
+
+    use pyo3::prelude::*;
+
+    use cavy::session::Config;
+
+    use crate::{
+        circuit::Transcript, gates::MeasMode, serialize, worker, CavyError, CavyTimeoutError,
+    };
+
+    /// The first byte of the child's message, saying what follows it: a
+    /// transcript, nothing, or the formatted compiler errors
+    const CIRCUIT: u8 = 0;
+    const NO_CIRCUIT: u8 = 1;
+    const ERRORS: u8 = 2;
+
+    pub(super) fn compile(
+        py: Python,
+        conf: &Config,
+        mode: MeasMode,
+        src: String,
+        timeout: Option<Duration>,
+    ) -> PyResult<Option<Transcript>> {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        let (read, write) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+        let pid = unsafe { libc::fork() };
+        if pid < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        if pid == 0 {
+            drop(read);
+            child(write, conf, mode, src);
+        }
+        drop(write);
+
+        let rx = worker::spawn(move || {
+            let mut msg = vec![];
+            let read = (&read).read_to_end(&mut msg);
+            let mut status = 0;
+            unsafe { libc::waitpid(pid, &mut status, 0) };
+            (read.map(|_| msg), status)
+        });
+        let (msg, status) = match worker::wait(py, &rx, timeout, &AtomicBool::new(false)) {
+            Ok(result) => result,
+            Err(err) => {
+                // The reader thread then reaps the child.
+                unsafe { libc::kill(pid, libc::SIGKILL) };
+                if err.is_instance::<CavyTimeoutError>(py) {
+                    let msg = format!(
+                        "compilation timed out after {} seconds, and was killed",
+                        timeout.unwrap().as_secs_f64()
+                    );
+                    return Err(PyErr::new::<CavyTimeoutError, _>(msg));
+                }
+                return Err(err);
+            }
+        };
+
+        if libc::WIFSIGNALED(status) {
+            let msg = format!(
+                "the compiler crashed, killed by signal {}",
+                libc::WTERMSIG(status)
+            );
+            return Err(PyErr::new::<CavyError, _>(msg));
+        }
+        if libc::WEXITSTATUS(status) != 0 {
+            let msg = format!(
+                "the compiler crashed, exiting with status {}",
+                libc::WEXITSTATUS(status)
+            );
+            return Err(PyErr::new::<CavyError, _>(msg));
+        }
+        match msg?.split_first() {
+            Some((&CIRCUIT, bytes)) => Ok(Some(serialize::transcript_from_bytes(bytes)?)),
+            Some((&NO_CIRCUIT, [])) => Ok(None),
+            Some((&ERRORS, errs)) => {
+                let errs = String::from_utf8_lossy(errs).into_owned();
+                Err(PyErr::new::<CavyError, _>(errs))
+            }
+            _ => Err(PyErr::new::<CavyError, _>(
+                "the compiler's process sent no result",
+            )),
+        }
+    }
+
+    /// Compile in the child, send the result to the parent, and exit, without
+    /// ever returning to the interpreter, even by unwinding
+    fn child(mut out: File, conf: &Config, mode: MeasMode, src: String) -> ! {
+        let msg = panic::catch_unwind(AssertUnwindSafe(|| {
+            let mut msg = vec![];
+            match worker::compile(conf, src) {
+                Ok(Some(circ)) => {
+                    msg.push(CIRCUIT);
+                    let bytes = serialize::transcript_to_bytes(&Transcript::new(circ, mode));
+                    msg.extend(bytes.ok()?);
+                }
+                Ok(None) => msg.push(NO_CIRCUIT),
+                Err(errs) => {
+                    msg.push(ERRORS);
+                    msg.extend(errs.into_bytes());
+                }
+            }
+            Some(msg)
+        }));
+        let status = match msg {
+            Ok(Some(msg)) if out.write_all(&msg).is_ok() => 0,
+            Ok(_) => 1,
+            Err(_) => 101,
+        };
+        unsafe { libc::_exit(status) }
+    }
+}
@@ -1,119 +1,53 @@
-use paste::paste;
-use pyo3::{class::basic::PyObjectProtocol, create_exception, prelude::*};
+mod cache;
+mod circuit;
+mod export;
+mod feedback;
+mod gates;
+mod hooks;
+mod interop;
+mod isolate;
+mod mapped;
+mod serialize;
+mod transform;
+mod watch;
+mod worker;
 
-use cavy::{
-    arch::{Arch, MeasurementMode},
-    cavy_errors::ErrorBuf,
-    circuit::{BaseGateQ, CircuitBuf, GateQ, Inst, Qbit},
-    context::Context,
-    session::{Config, OptConfig, OptFlags, Phase, PhaseConfig, Statistics},
-    util::FmtWith,
+use std::{
+    path::PathBuf,
+    sync::{atomic::AtomicBool, Arc, Mutex},
+    time::Instant,
 };
 
-create_exception!(pycavy, CavyError, pyo3::exceptions::PyException);
-
-#[pyclass(subclass)]
-struct Gate {}
-
-#[pymethods]
-impl Gate {
-    #[new]
-    fn new() -> Self {
-        Self {}
-    }
-}
-
-macro_rules! gates {
-    ($module:ident < $($name:ident[$qbs:expr]),*) => {
-        $(
-
-        paste! {
-            #[pyclass(extends=Gate, subclass)]
-            /// A quantum gate implementing the named operation
-            struct [<$name Gate>] {
-                // Could consider adding a `set` to this
-                #[pyo3(get)]
-                qbs: [usize; $qbs],
-            }
-
-            impl [<$name Gate>] {
-                fn pyobj<'p>(py: Python<'p>, qbs: [Qbit; $qbs]) -> &PyAny {
-                    let mut new_qbs = [0; $qbs];
-                    for i in 0..$qbs {
-                        new_qbs[i] = <u32>::from(qbs[i]) as usize;
-                    }
-                    PyCell::new(py, Self::new(new_qbs))
-                        .unwrap()
-                        .as_ref()
-                }
-            }
-
-            #[pymethods]
-            impl [<$name Gate>] {
-                #[new]
-                fn new(qbs: [usize; $qbs]) -> (Self, Gate) {
-                    (Self { qbs }, Gate::new())
-                }
-            }
-
-            #[pyproto]
-            impl PyObjectProtocol for [<$name Gate>] {
-                fn __repr__(&self) -> PyResult<String> {
-                    Ok(format!("{}{:?}", stringify!($name), self.qbs))
-                }
-
-                fn __str__(&self) -> PyResult<String> {
-                    self.__repr__()
-                }
-            }
-        }
-        )*
-    };
-}
+use pyo3::{
+    class::gc::PyGCProtocol,
+    create_exception,
+    exceptions::PyValueError,
+    prelude::*,
+    types::{PyBytes, PyDict, PyString},
+    wrap_pyfunction, PyTraverseError, PyVisit,
+};
 
-gates! { m <
-    H[1], Z[1], X[1], T[1], TDag[1], CX[2], SWAP[2]
-}
+use rayon::prelude::*;
 
-fn circuit_to_py(py: Python, circ: CircuitBuf) -> PyResult<Vec<&PyAny>> {
-    let transcribe_base_gate = |gate| match gate {
-        BaseGateQ::X(u) => XGate::pyobj(py, [u]),
-        BaseGateQ::T(u) => TGate::pyobj(py, [u]),
-        BaseGateQ::H(u) => HGate::pyobj(py, [u]),
-        BaseGateQ::Z(u) => ZGate::pyobj(py, [u]),
-        BaseGateQ::TDag(u) => TDagGate::pyobj(py, [u]),
-        BaseGateQ::Cnot { tgt, ctrl } => CXGate::pyobj(py, [ctrl, tgt]),
-        BaseGateQ::Swap(fst, snd) => SWAPGate::pyobj(py, [fst, snd]),
-    };
+use cavy::{
+    arch::{Arch, MeasurementMode},
+    circuit::CircuitBuf,
+    session::{Config, OptConfig, OptFlags, Phase, PhaseConfig},
+};
 
-    let transcribe_gate = |gate: GateQ| {
-        let base = transcribe_base_gate(gate.base);
-        if gate.ctrls.is_empty() {
-            base
-        } else {
-            // FIXME not handled yet
-            panic!();
-        }
-    };
+use crate::{
+    cache::Cache,
+    circuit::{Circuit, GateIter},
+    gates::*,
+    hooks::{Hooks, Outcome},
+    mapped::MappedCircuit,
+    watch::Watcher,
+    worker::CompileHandle,
+};
 
-    // What if there are infinitely many gates?
-    let gates = circ
-        .into_iter()
-        .filter_map(|inst| match inst {
-            Inst::CInit(_) => None,
-            Inst::CFree(_, _) => None,
-            Inst::QInit(_) => None,
-            Inst::QFree(_, _) => None,
-            Inst::QGate(gate) => Some(transcribe_gate(gate)),
-            Inst::CGate(_) => {
-                todo!()
-            }
-            Inst::Meas(_, _) => None,
-            Inst::Out(_) => None,
-        })
-        .collect();
-    Ok(gates)
-}
+create_exception!(pycavy, CavyError, pyo3::exceptions::PyException);
+create_exception!(pycavy, CavyTimeoutError, CavyError);
+create_exception!(pycavy, CavyAbandonedError, CavyError);
 
 fn get_meas_mode(mode: &str) -> Result<MeasurementMode, ()> {
     let mode = match mode {
@@ -154,9 +88,75 @@ fn opt_flag(input: Option<bool>) -> i8 {
     }
 }
 
-#[pyclass]
-struct Session {
-    conf: Config,
+/// Decode UTF-8 source text, reporting the offsets of any invalid bytes
+fn decode_source(bytes: &[u8]) -> PyResult<String> {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => Ok(text.to_owned()),
+        Err(err) => {
+            let start = err.valid_up_to();
+            let end = start + err.error_len().unwrap_or(bytes.len() - start);
+            let msg = format!(
+                "source is not valid UTF-8: invalid bytes {:?} at offsets {}..{}",
+                &bytes[start..end],
+                start,
+                end
+            );
+            Err(PyErr::new::<CavyError, _>(msg))
+        }
+    }
+}
+
+/// Convert a `str`, `bytes`, or `os.PathLike` path to a native one
+pub(crate) fn fs_path(py: Python, path: &PyAny) -> PyResult<PathBuf> {
+    let path: String = py.import("os")?.call1("fsdecode", (path,))?.extract()?;
+    Ok(PathBuf::from(path))
+}
+
+/// Read Cavy source text out of a Python object, which may be a `str`, a
+/// `bytes` holding UTF-8, an `os.PathLike` naming a source file, or a file-like
+/// object with a `read` method returning either of the former.
+fn source_text(py: Python, src: &PyAny) -> PyResult<String> {
+    if let Ok(bytes) = src.downcast::<PyBytes>() {
+        return decode_source(bytes.as_bytes());
+    }
+    if let Ok(text) = src.extract::<String>() {
+        return Ok(text);
+    }
+    if src.hasattr("__fspath__")? {
+        let path = fs_path(py, src)?;
+        return match std::fs::read(&path) {
+            Ok(bytes) => decode_source(&bytes),
+            Err(err) => {
+                let msg = format!("could not read '{}': {}", path.display(), err);
+                Err(PyErr::new::<CavyError, _>(msg))
+            }
+        };
+    }
+    if src.hasattr("read")? {
+        let contents = src.call_method0("read")?;
+        if contents.is_instance::<PyBytes>()? || contents.is_instance::<PyString>()? {
+            return source_text(py, contents);
+        }
+    }
+    let msg = format!(
+        "expected Cavy source as str, bytes, a path, or a readable file, not '{}'",
+        src.get_type().name()?
+    );
+    Err(PyErr::new::<CavyError, _>(msg))
+}
+
+#[pyclass(gc)]
+pub(crate) struct Session {
+    /// Shared with the threads compiling in this session
+    conf: Arc<Config>,
+    /// Whether identical gates should share a single Python object
+    intern_gates: bool,
+    cache: Mutex<Cache>,
+    /// Source prepended to every program compiled in this session
+    prelude: Option<String>,
+    hooks: Hooks,
+    /// The fingerprint of the compiler options, for the headers of exports
+    fingerprint: u64,
 }
 
 /// A Cavy compilation session, whose constructor accepts compiler options to
@@ -173,9 +173,13 @@ impl Session {
         meas_mode = "\"nondemolition\"",
         feedback = "false",
         recursion = "false",
-        phase = "None"
+        phase = "None",
+        intern_gates = "false",
+        cache_size = "0",
+        prelude = "None"
     )]
     fn new(
+        py: Python,
         opt_level: u8,
         const_prop: Option<bool>,
         debug: bool,
@@ -186,7 +190,26 @@ impl Session {
         feedback: bool,
         recursion: bool,
         phase: Option<&str>,
-    ) -> Self {
+        // binding options
+        intern_gates: bool,
+        cache_size: usize,
+        prelude: Option<&PyAny>,
+    ) -> PyResult<Self> {
+        // Every option that changes the circuit compiled from a source, and
+        // none that only change how it's handed to Python
+        let fingerprint = export::provenance::fingerprint(&format!(
+            "opt_level={} const_prop={:?} debug={} qb_count={:?} qram_size={} \
+             meas_mode={} feedback={} recursion={} phase={:?}",
+            opt_level,
+            const_prop,
+            debug,
+            qb_count,
+            qram_size,
+            meas_mode,
+            feedback,
+            recursion,
+            phase
+        ));
         let phase_config = get_phase(phase);
         let meas_mode = get_meas_mode(meas_mode).unwrap();
         let arch = Arch {
@@ -208,33 +231,381 @@ impl Session {
             opt,
             phase_config,
         };
-        Self { conf }
+        let prelude = prelude
+            .map(|prelude| source_text(py, prelude))
+            .transpose()?;
+        Ok(Self {
+            conf: Arc::new(conf),
+            intern_gates,
+            cache: Mutex::new(Cache::new(cache_size)),
+            prelude,
+            hooks: Hooks::default(),
+            fingerprint,
+        })
+    }
+
+    #[getter]
+    fn opt_level(&self) -> u8 {
+        self.conf.opt.level
+    }
+
+    #[getter]
+    fn debug(&self) -> bool {
+        self.conf.debug
+    }
+
+    #[getter]
+    fn qram_size(&self) -> usize {
+        self.conf.arch.qram_size
+    }
+
+    #[getter]
+    fn meas_mode(&self) -> &'static str {
+        self.measurement_mode().name()
+    }
+
+    #[getter]
+    fn feedback(&self) -> bool {
+        self.conf.arch.feedback
+    }
+
+    #[getter]
+    fn recursion(&self) -> bool {
+        self.conf.arch.recursion
+    }
+
+    #[getter]
+    fn intern_gates(&self) -> bool {
+        self.intern_gates
+    }
+
+    #[getter]
+    fn prelude(&self) -> Option<&str> {
+        self.prelude.as_deref()
+    }
+
+    /// Compile Cavy source, given as a `str`, as UTF-8 encoded `bytes`, as a path
+    /// to a source file, or as a readable file object.
+    ///
+    /// Returns a `Circuit`, or `None`, rather than an empty circuit, if the
+    /// program produced no circuit: for instance, if compilation stopped at an
+    /// earlier `phase`.
+    ///
+    /// If `timeout`, in seconds, is given and compilation takes longer, raises
+    /// a `CavyTimeoutError`. This only stops waiting: the compiler can't be
+    /// stopped partway through, so the timed-out compilation is abandoned on
+    /// its thread, which keeps running, with its CPU time and memory, until
+    /// the compiler finishes. Programs that repeatedly time out therefore pile
+    /// up threads, and one that never finishes compiling holds its thread for
+    /// the life of the process. The same goes for a compilation interrupted by
+    /// `KeyboardInterrupt`.
+    ///
+    /// If `on_instruction` is given, it's instead called with each gate in
+    /// turn, as it's transcribed from the compiler's output, and no `Circuit`
+    /// is built; `compile` then returns `None`. An exception raised by the
+    /// callback stops the transcription and is propagated.
+    ///
+    /// If the session was created with a nonzero `cache_size`, this many of
+    /// the most recently compiled circuits are cached by their source, and
+    /// compiling the same source again returns the same `Circuit` object
+    /// without running the compiler. Only `compile`, without `on_instruction`,
+    /// uses the cache.
+    ///
+    /// If the session was created with a `prelude`, given as source text or as
+    /// a path to a source file, its declarations are available to every
+    /// program compiled in the session. The prelude is prepended to each
+    /// program's source, so line numbers in compiler errors count its lines
+    /// too.
+    ///
+    /// With `isolated=True`, the program is compiled in a child process forked
+    /// from this one, on Unix only, so that a compiler that crashes or aborts
+    /// raises a `CavyError` rather than killing the interpreter. The circuit is
+    /// sent back to this process serialized. A timeout, or an interruption,
+    /// kills the child process, so an isolated compilation doesn't keep
+    /// running after it's abandoned. `on_instruction` can't be combined with
+    /// isolation.
+    #[args(timeout = "None", on_instruction = "None", isolated = "false")]
+    fn compile(
+        slf: PyRef<Self>,
+        py: Python,
+        src: &PyAny,
+        timeout: Option<f64>,
+        on_instruction: Option<&PyAny>,
+        isolated: bool,
+    ) -> PyResult<Option<Py<Circuit>>> {
+        if isolated && on_instruction.is_some() {
+            return Err(PyValueError::new_err(
+                "on_instruction can't be used with isolated=True",
+            ));
+        }
+        let src = slf.source(py, src)?;
+        let session: Py<Session> = slf.into();
+        let start = Instant::now();
+        let circ = Self::compile_source(&session, py, &src, timeout, on_instruction, isolated);
+        let outcome = Outcome::of(py, start.elapsed(), &circ);
+        session.borrow(py).hooks.after(py, &src, outcome)?;
+        circ
+    }
+
+    /// Compile Cavy source like `compile`, but return an iterator that builds
+    /// each gate object only as it's reached, rather than a `Circuit`. For very
+    /// large programs, this bounds the memory the bindings use, although the
+    /// compiler still produces its own instructions all at once.
+    fn compile_iter(&self, py: Python, src: &PyAny) -> PyResult<Option<GateIter>> {
+        let src = self.source(py, src)?;
+        let start = Instant::now();
+        let circ = self.compile_buf(py, src.clone(), None);
+        let outcome = Outcome {
+            elapsed: start.elapsed(),
+            circuit: py.None(),
+            error: circ.as_ref().err().map(|err| err.instance(py)).into_py(py),
+        };
+        self.hooks.after(py, &src, outcome)?;
+        Ok(circ?.map(|circ| GateIter::new(circ, self.intern_gates, self.measurement_mode())))
+    }
+
+    /// Start compiling Cavy source in the background, returning a
+    /// `CompileHandle` whose `result` waits for the circuit, and whose
+    /// `abandon` stops waiting for it from any thread. Abandoning a
+    /// compilation leaves its thread running until the compiler finishes.
+    fn compile_background(slf: PyRef<Self>, py: Python, src: &PyAny) -> PyResult<CompileHandle> {
+        let src = slf.source(py, src)?;
+        let conf = Arc::clone(&slf.conf);
+        let job = src.clone();
+        let rx = worker::spawn(move || {
+            let start = Instant::now();
+            (worker::compile(&conf, job), start.elapsed())
+        });
+        let intern = slf.intern_gates;
+        Ok(CompileHandle::new(rx, src, slf.into(), intern))
     }
 
-    fn compile<'a>(&self, py: Python<'a>, src: String) -> PyResult<Vec<&'a PyAny>> {
-        let mut stats = Statistics::new();
-        let mut ctx = Context::new(&self.conf, &mut stats);
+    /// The same as `compile_background`
+    fn compile_cancellable(slf: PyRef<Self>, py: Python, src: &PyAny) -> PyResult<CompileHandle> {
+        Self::compile_background(slf, py, src)
+    }
+
+    /// Watch a source file, or the files directly in a directory whose names
+    /// end with `suffix`, compiling each when watching starts and again
+    /// whenever its modification time or size changes. Each compilation is as
+    /// `compile` would do it, with the session's prelude, cache and hooks, and
+    /// calls `on_compile` with the file's path and either the circuit or the
+    /// `CavyError` that compiling it raised.
+    ///
+    /// The files are polled every `interval` seconds on a background thread,
+    /// which holds the GIL only to compile and call back. Returns a `Watcher`,
+    /// whose `stop` ends the watching. An exception raised by the callback or
+    /// a hook also ends it, and is raised by `stop`.
+    #[args(on_compile, interval = "0.5", suffix = "\".cav\"")]
+    fn watch(
+        slf: PyRef<Self>,
+        py: Python,
+        path: &PyAny,
+        on_compile: PyObject,
+        interval: f64,
+        suffix: &str,
+    ) -> PyResult<Watcher> {
+        let path = fs_path(py, path)?;
+        Watcher::start(slf.into(), path, on_compile, interval, suffix.to_owned())
+    }
+
+    /// Counts of the cache's `hits` and `misses` so far, with its current
+    /// `size` and its `max_size`
+    fn cache_stats<'p>(&self, py: Python<'p>) -> PyResult<&'p PyDict> {
+        let (hits, misses, size, max_size) = self.cache.lock().unwrap().stats();
+        let stats = PyDict::new(py);
+        stats.set_item("hits", hits)?;
+        stats.set_item("misses", misses)?;
+        stats.set_item("size", size)?;
+        stats.set_item("max_size", max_size)?;
+        Ok(stats)
+    }
+
+    /// Empty the cache of compiled circuits, keeping its hit and miss counts
+    fn clear_cache(&self) {
+        self.cache.lock().unwrap().clear();
+    }
 
-        match self.compile_inner(&mut ctx, src) {
-            Ok(Some(circ)) => circuit_to_py(py, circ),
-            Ok(None) => Ok(vec![]),
-            Err(errs) => {
-                let errs = format!("{}", errs.fmt_with(&ctx));
-                let py_err = PyErr::new::<CavyError, _>(errs);
-                Err(py_err)
+    /// Register callbacks to run around each compilation in this session, for
+    /// enforcing quotas or keeping an audit log. Each is called with a
+    /// dictionary describing the program: its full `source`, including any
+    /// prelude, with its `size` in bytes and its number of `lines`.
+    ///
+    /// `before` is called before a program is compiled by any of the compile
+    /// methods, including on a cache hit, and can refuse it by raising an
+    /// exception, which the compile method then raises in turn.
+    ///
+    /// `after` is called once any of them has a result for a program, with
+    /// the dictionary also holding the `elapsed` time in seconds, the
+    /// `circuit` returned, its `n_qubits` and its number of `instructions`,
+    /// and the `error` raised, each of which but `elapsed` may be `None`.
+    /// `compile_iter`, and `compile` with `on_instruction`, build no
+    /// `Circuit`, so they report none of these but the `error`. For
+    /// `compile_background`, the hook runs in whichever thread first gets the
+    /// result from the `CompileHandle`, and `elapsed` is only the time spent
+    /// compiling.
+    #[args(before = "None", after = "None")]
+    fn add_hook(&self, before: Option<PyObject>, after: Option<PyObject>) {
+        self.hooks.add(before, after);
+    }
+
+    /// Compile many independent Cavy sources in parallel, returning, in order,
+    /// what `compile` would have returned for each, or the `CavyError` it would
+    /// have raised.
+    fn compile_many(slf: PyRef<Self>, py: Python, srcs: Vec<&PyAny>) -> PyResult<Vec<PyObject>> {
+        let srcs = srcs
+            .into_iter()
+            .map(|src| slf.source(py, src))
+            .collect::<PyResult<Vec<_>>>()?;
+        let conf = Arc::clone(&slf.conf);
+        let jobs = srcs.clone();
+        let rx = worker::spawn(move || {
+            jobs.into_par_iter()
+                .map(|src| {
+                    let start = Instant::now();
+                    (worker::compile(&conf, src), start.elapsed())
+                })
+                .collect::<Vec<_>>()
+        });
+        let circs = worker::wait(py, &rx, None, &AtomicBool::new(false))?;
+
+        let intern = slf.intern_gates;
+        let session: Py<Session> = slf.into();
+        srcs.iter()
+            .zip(circs)
+            .map(|(src, (circ, elapsed))| {
+                let (circuit, error) = match circ {
+                    Ok(Some(circ)) => {
+                        let circ = Circuit::new(py, circ, src, session.clone_ref(py), intern);
+                        (Py::new(py, circ)?.into_py(py), py.None())
+                    }
+                    Ok(None) => (py.None(), py.None()),
+                    Err(errs) => {
+                        let err = PyErr::new::<CavyError, _>(errs).instance(py).into_py(py);
+                        (py.None(), err)
+                    }
+                };
+                let result = if error.is_none(py) {
+                    circuit.clone_ref(py)
+                } else {
+                    error.clone_ref(py)
+                };
+                let outcome = Outcome {
+                    elapsed,
+                    circuit,
+                    error,
+                };
+                session.borrow(py).hooks.after(py, src, outcome)?;
+                Ok(result)
+            })
+            .collect()
+    }
+}
+
+/// Cached circuits, and quite possibly hooks, refer back to their session, so
+/// the session has to take part in garbage collection for it to be freed.
+#[pyproto]
+impl PyGCProtocol for Session {
+    fn __traverse__(&self, visit: PyVisit) -> Result<(), PyTraverseError> {
+        // A cache in use by another thread is skipped, which only delays
+        // collecting the session
+        if let Ok(cache) = self.cache.try_lock() {
+            for circ in cache.circuits() {
+                visit.call(circ)?;
             }
         }
+        self.hooks.traverse(&visit)
+    }
+
+    fn __clear__(&mut self) {
+        self.cache.lock().unwrap().clear();
+        self.hooks.clear();
     }
 }
 
 impl Session {
-    fn compile_inner(
+    pub(crate) fn fingerprint(&self) -> u64 {
+        self.fingerprint
+    }
+
+    pub(crate) fn measurement_mode(&self) -> MeasMode {
+        match self.conf.arch.meas_mode {
+            MeasurementMode::Nondemolition => MeasMode::Nondemolition,
+            MeasurementMode::Demolition => MeasMode::Demolition,
+        }
+    }
+
+    /// The full text to compile for a program: its source, after the prelude.
+    /// This is also where the `before` hooks get to refuse it.
+    fn source(&self, py: Python, src: &PyAny) -> PyResult<String> {
+        let src = source_text(py, src)?;
+        let src = match &self.prelude {
+            Some(prelude) => format!("{}\n{}", prelude, src),
+            None => src,
+        };
+        self.hooks.before(py, &src)?;
+        Ok(src)
+    }
+
+    fn compile_source(
+        session: &Py<Session>,
+        py: Python,
+        src: &str,
+        timeout: Option<f64>,
+        on_instruction: Option<&PyAny>,
+        isolated: bool,
+    ) -> PyResult<Option<Py<Circuit>>> {
+        let slf = session.borrow(py);
+        let caching = on_instruction.is_none() && slf.cache.lock().unwrap().enabled();
+        if caching {
+            if let Some(circ) = slf.cache.lock().unwrap().get(py, src) {
+                return Ok(circ);
+            }
+        }
+        let key = if caching { Some(src.to_owned()) } else { None };
+
+        let intern = slf.intern_gates;
+        let circ = if isolated {
+            let timeout = worker::parse_timeout(timeout)?;
+            let mode = slf.measurement_mode();
+            isolate::compile(py, &slf.conf, mode, src.to_owned(), timeout)?
+                .map(|circ| Circuit::from_transcript(circ, src, session.clone_ref(py), intern))
+        } else {
+            match (
+                slf.compile_buf(py, src.to_owned(), timeout)?,
+                on_instruction,
+            ) {
+                (Some(circ), Some(callback)) => {
+                    let mut gates = GateIter::new(circ, intern, slf.measurement_mode());
+                    while let Some(gate) = gates.next_obj(py)? {
+                        callback.call1((gate,))?;
+                    }
+                    return Ok(None);
+                }
+                (circ, _) => {
+                    circ.map(|circ| Circuit::new(py, circ, src, session.clone_ref(py), intern))
+                }
+            }
+        };
+        let circ = circ.map(|circ| Py::new(py, circ)).transpose()?;
+        if let Some(key) = key {
+            let cached = circ.as_ref().map(|circ| circ.clone_ref(py));
+            slf.cache.lock().unwrap().insert(key, cached);
+        }
+        Ok(circ)
+    }
+
+    fn compile_buf(
         &self,
-        ctx: &mut Context,
+        py: Python,
         src: String,
-    ) -> Result<Option<CircuitBuf>, ErrorBuf> {
-        let id = ctx.srcs.insert_input(&src);
-        cavy::compile::compile_circuit(id, ctx)
+        timeout: Option<f64>,
+    ) -> PyResult<Option<CircuitBuf>> {
+        let timeout = worker::parse_timeout(timeout)?;
+        let conf = Arc::clone(&self.conf);
+        let rx = worker::spawn(move || worker::compile(&conf, src));
+        let circ = worker::wait(py, &rx, timeout, &AtomicBool::new(false))?;
+        circ.map_err(PyErr::new::<CavyError, _>)
     }
 }
 
@@ -242,14 +613,33 @@ impl Session {
 #[pymodule]
 fn pycavy(py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<Session>()?;
+    m.add_class::<Circuit>()?;
+    m.add_class::<MappedCircuit>()?;
+    m.add_class::<CompileHandle>()?;
+    m.add_class::<Watcher>()?;
     m.add_class::<Gate>()?;
     m.add_class::<HGate>()?;
     m.add_class::<ZGate>()?;
     m.add_class::<XGate>()?;
     m.add_class::<TGate>()?;
+    m.add_class::<TDagGate>()?;
     m.add_class::<CXGate>()?;
+    m.add_class::<SWAPGate>()?;
+    m.add_class::<MeasGate>()?;
+    m.add_class::<ClassicalInst>()?;
+    m.add_class::<CInitGate>()?;
+    m.add_class::<CFreeGate>()?;
+    m.add_class::<CGate>()?;
+    m.add_class::<OutGate>()?;
+    m.add_class::<AllocInst>()?;
+    m.add_class::<QInitGate>()?;
+    m.add_class::<QFreeGate>()?;
+    m.add_function(wrap_pyfunction!(set_repr_style, m)?)?;
 
     m.add("CavyError", py.get_type::<CavyError>())?;
+    m.add("CavyTimeoutError", py.get_type::<CavyTimeoutError>())?;
+    m.add("CavyAbandonedError", py.get_type::<CavyAbandonedError>())?;
+    m.add("CavyCancelledError", py.get_type::<CavyAbandonedError>())?;
     m.add("__version__", env!("CARGO_PKG_VERSION"))?;
     Ok(())
 }
@@ -0,0 +1,187 @@
+//! Circuits read lazily from memory-mapped files, for analyzing circuits too
+//! large to load.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Write},
+};
+
+use pyo3::{
+    class::{
+        basic::PyObjectProtocol, iter::PyIterProtocol, mapping::PyMappingProtocol,
+        sequence::PySequenceProtocol,
+    },
+    prelude::*,
+};
+
+use crate::{
+    circuit::{checked_index, Circuit, Index, Instruction},
+    export::{
+        self,
+        provenance::{self, Provenance},
+    },
+    fs_path,
+    serialize::Mapped,
+};
+
+/// A circuit in a file written by `Circuit.save`, as returned by
+/// `Circuit.load` with `mmap=True`. The file is mapped into memory, and each
+/// instruction is decoded only when it's asked for, so that this behaves like
+/// a read-only `Circuit` without ever holding the whole circuit in memory.
+/// Slicing it yields an ordinary `Circuit` of just those instructions.
+///
+/// The file must not be truncated or rewritten in place while this is alive:
+/// reading a page of the mapping that's past the file's new end raises
+/// `SIGBUS`, which kills the process rather than raising an exception.
+#[pyclass]
+pub(crate) struct MappedCircuit {
+    mapped: Mapped,
+}
+
+impl MappedCircuit {
+    pub(crate) fn new(mapped: Mapped) -> Self {
+        Self { mapped }
+    }
+
+    fn insts(&self) -> impl Iterator<Item = PyResult<Instruction>> + '_ {
+        (0..self.mapped.len()).map(move |i| self.mapped.get(i))
+    }
+}
+
+/// The name an instruction is counted under by `counts`
+fn count_name(inst: &Instruction) -> &'static str {
+    match inst {
+        Instruction::Gate { kind, .. } => kind.name(),
+        Instruction::Meas { .. } => "Meas",
+        Instruction::CInit(_) => "CInit",
+        Instruction::CFree { .. } => "CFree",
+        Instruction::CGate { kind, .. } => kind.name(),
+        Instruction::Out { .. } => "Out",
+    }
+}
+
+#[pymethods]
+impl MappedCircuit {
+    /// The number of qubits allocated or acted on by the circuit
+    #[getter]
+    fn n_qubits(&self) -> usize {
+        self.mapped.n_qubits()
+    }
+
+    /// The instructions from `start` up to `stop`, or the end, as a `Circuit`.
+    /// As with slices, out-of-range bounds are clamped.
+    #[args(stop = "None")]
+    fn slice(&self, start: usize, stop: Option<usize>) -> PyResult<Circuit> {
+        let len = self.mapped.len();
+        let stop = stop.unwrap_or(len).min(len);
+        let start = start.min(stop);
+        let insts = (start..stop)
+            .map(|i| self.mapped.get(i))
+            .collect::<PyResult<_>>()?;
+        Ok(Circuit::from_insts(insts, self.n_qubits(), None, false))
+    }
+
+    /// The number of instructions of each kind, as a dictionary from gate
+    /// names (as in `Gate` reprs), classical gate operations (`"not"`,
+    /// `"copy"`, and `"swap"`), and `"Meas"`, `"CInit"`, `"CFree"`, and `"Out"`
+    fn counts(&self) -> PyResult<HashMap<&'static str, usize>> {
+        let mut counts = HashMap::new();
+        for inst in self.insts() {
+            *counts.entry(count_name(&inst?)).or_insert(0) += 1;
+        }
+        Ok(counts)
+    }
+
+    /// Write the circuit to a file as an OpenQASM 2.0 program, as
+    /// `Circuit.to_qasm2` would, one instruction at a time. A saved circuit
+    /// keeps no session or source, so its header has neither fingerprint.
+    #[args(timestamp = "true")]
+    fn write_qasm2(&self, py: Python, path: &PyAny, timestamp: bool) -> PyResult<()> {
+        let mut out = BufWriter::new(File::create(fs_path(py, path)?)?);
+        let provenance = Provenance {
+            config: None,
+            source: None,
+        };
+        let header = provenance::header("//", &provenance, timestamp);
+        export::qasm::write_qasm2(&mut out, &header, || self.insts(), self.n_qubits())?;
+        out.flush()?;
+        Ok(())
+    }
+}
+
+#[pyproto]
+impl PySequenceProtocol for MappedCircuit {
+    fn __len__(&self) -> usize {
+        self.mapped.len()
+    }
+
+    fn __getitem__(&self, idx: isize) -> PyResult<PyObject> {
+        let index = checked_index(idx, self.mapped.len())?;
+        Python::with_gil(|py| self.mapped.get(index)?.to_py(py))
+    }
+}
+
+#[pyproto]
+impl PyMappingProtocol for MappedCircuit {
+    fn __getitem__(&self, key: &PyAny) -> PyResult<PyObject> {
+        Python::with_gil(|py| match Index::extract(key, self.mapped.len())? {
+            Index::Single(index) => self.mapped.get(index)?.to_py(py),
+            Index::Slice(indices) => {
+                let insts = indices
+                    .map(|i| self.mapped.get(i))
+                    .collect::<PyResult<_>>()?;
+                let circ = Circuit::from_insts(insts, self.n_qubits(), None, false);
+                Ok(Py::new(py, circ)?.into_py(py))
+            }
+        })
+    }
+}
+
+#[pyproto]
+impl PyIterProtocol for MappedCircuit {
+    fn __iter__(slf: PyRef<Self>) -> PyResult<Py<MappedIter>> {
+        let iter = MappedIter {
+            circuit: slf.into(),
+            index: 0,
+        };
+        Python::with_gil(|py| Py::new(py, iter))
+    }
+}
+
+#[pyproto]
+impl PyObjectProtocol for MappedCircuit {
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!(
+            "MappedCircuit(<{} instructions on {} qubits>)",
+            self.mapped.len(),
+            self.n_qubits()
+        ))
+    }
+}
+
+#[pyclass]
+pub(crate) struct MappedIter {
+    circuit: Py<MappedCircuit>,
+    index: usize,
+}
+
+#[pyproto]
+impl PyIterProtocol for MappedIter {
+    fn __iter__(slf: PyRef<Self>) -> PyRef<Self> {
+        slf
+    }
+
+    fn __next__(mut slf: PyRefMut<Self>) -> PyResult<Option<PyObject>> {
+        let py = slf.py();
+        let index = slf.index;
+        let circuit = slf.circuit.as_ref(py).borrow();
+        if index >= circuit.mapped.len() {
+            return Ok(None);
+        }
+        let obj = circuit.mapped.get(index)?.to_py(py)?;
+        drop(circuit);
+        slf.index += 1;
+        Ok(Some(obj))
+    }
+}
@@ -0,0 +1,696 @@
+//! Serialization of compiled circuits, for storing them and exchanging them
+//! between processes.
+//!
+//! The JSON schema is an object holding the schema `version` (currently 1),
+//! `n_qubits`, and a list of `instructions`. Each instruction is an object
+//! whose `kind` is one of
+//!
+//! * `"gate"`, with the `gate` name (`"H"`, `"Z"`, `"X"`, `"T"`, `"TDag"`,
+//!   `"CX"`, or `"SWAP"`), its target qubits `qbs`, and its control qubits
+//!   `ctrls`;
+//! * `"meas"`, with the measured qubit `qb`, the classical bit `cb`, and the
+//!   `mode`, `"nondemolition"` (the default) or `"demolition"`;
+//! * `"c_init"`, with the classical bit `cb`;
+//! * `"c_free"`, with the classical bit `cb` and the compiler's `flag` on it;
+//! * `"c_gate"`, with the operation `op` (`"not"`, `"copy"`, or `"swap"`), its
+//!   target bits `cbs`, and its control bits `ctrls`;
+//! * `"out"`, with the output bit `cb`, and the `name` and element `elem` of
+//!   the output.
+//!
+//! The schema is kept separate from `Instruction`, so that it only changes
+//! when the version does.
+//!
+//! The binary format is the `MAGIC` bytes and a little-endian `u32` format
+//! version, followed by the `bincode` encoding, with variable-length
+//! integers, of the qubit count and a list of `BinInst`s. Like the JSON
+//! schema, `BinInst` is kept separate from `Instruction`; since `bincode`
+//! encodes enums by position, its variants and the code tables below may only
+//! ever be appended to, and anything else must bump `BINARY_VERSION`.
+//!
+//! Files written by `save` hold the same bytes, followed by an index from
+//! which `Mapped` can find any instruction without decoding those before it:
+//! the little-endian `u64` offset of each instruction, the offset of the index
+//! itself, and the `INDEX_MAGIC` bytes. Both are written and read
+//! incrementally, so that a large circuit is never held in memory twice.
+//!
+//! A `Transcript` is encoded as its circuit, in the binary format, followed by
+//! the `bincode` encoding of its list of allocations, as `BinAlloc`s with the
+//! indices of the instructions they come before. It's only ever exchanged with
+//! a child process built from the same code, so it has no version of its own.
+
+use std::{
+    collections::HashSet,
+    convert::TryFrom,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use bincode::Options;
+use pyo3::{exceptions::PyValueError, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    circuit::{Alloc, Instruction, Transcript},
+    gates::{CGateKind, GateKind, MeasMode},
+};
+
+/// The current version of the schema
+const VERSION: u32 = 1;
+
+/// The first bytes of every binary-encoded circuit
+const MAGIC: &[u8; 4] = b"CAVY";
+
+/// The current version of the binary format
+const BINARY_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum InstSchema {
+    Gate {
+        gate: GateKind,
+        qbs: Vec<usize>,
+        ctrls: Vec<usize>,
+    },
+    Meas {
+        qb: usize,
+        cb: usize,
+        #[serde(default)]
+        mode: MeasMode,
+    },
+    CInit {
+        cb: usize,
+    },
+    CFree {
+        cb: usize,
+        flag: bool,
+    },
+    CGate {
+        op: CGateKind,
+        cbs: Vec<usize>,
+        ctrls: Vec<usize>,
+    },
+    Out {
+        cb: usize,
+        name: String,
+        elem: usize,
+    },
+}
+
+impl From<&Instruction> for InstSchema {
+    fn from(inst: &Instruction) -> Self {
+        match inst.clone() {
+            Instruction::Gate { kind, qbs, ctrls } => Self::Gate {
+                gate: kind,
+                qbs,
+                ctrls,
+            },
+            Instruction::Meas { qb, cb, mode } => Self::Meas { qb, cb, mode },
+            Instruction::CInit(cb) => Self::CInit { cb },
+            Instruction::CFree { cb, flag } => Self::CFree { cb, flag },
+            Instruction::CGate { kind, cbs, ctrls } => Self::CGate {
+                op: kind,
+                cbs,
+                ctrls,
+            },
+            Instruction::Out { cb, name, elem } => Self::Out { cb, name, elem },
+        }
+    }
+}
+
+impl From<InstSchema> for Instruction {
+    fn from(inst: InstSchema) -> Self {
+        match inst {
+            InstSchema::Gate { gate, qbs, ctrls } => Self::Gate {
+                kind: gate,
+                qbs,
+                ctrls,
+            },
+            InstSchema::Meas { qb, cb, mode } => Self::Meas { qb, cb, mode },
+            InstSchema::CInit { cb } => Self::CInit(cb),
+            InstSchema::CFree { cb, flag } => Self::CFree { cb, flag },
+            InstSchema::CGate { op, cbs, ctrls } => Self::CGate {
+                kind: op,
+                cbs,
+                ctrls,
+            },
+            InstSchema::Out { cb, name, elem } => Self::Out { cb, name, elem },
+        }
+    }
+}
+
+/// The binary encoding of an instruction, with gates and modes given by
+/// their positions in the code tables
+#[derive(Serialize, Deserialize)]
+enum BinInst {
+    Gate {
+        code: u8,
+        qbs: Vec<usize>,
+        ctrls: Vec<usize>,
+    },
+    Meas {
+        qb: usize,
+        cb: usize,
+        mode: u8,
+    },
+    CInit {
+        cb: usize,
+    },
+    CFree {
+        cb: usize,
+        flag: bool,
+    },
+    CGate {
+        code: u8,
+        cbs: Vec<usize>,
+        ctrls: Vec<usize>,
+    },
+    Out {
+        cb: usize,
+        name: String,
+        elem: usize,
+    },
+}
+
+/// The binary codes of gates, measurement modes, and classical gates, which
+/// are their positions here
+const GATE_CODES: &[GateKind] = &[
+    GateKind::H,
+    GateKind::Z,
+    GateKind::X,
+    GateKind::T,
+    GateKind::TDag,
+    GateKind::CX,
+    GateKind::SWAP,
+];
+const MEAS_CODES: &[MeasMode] = &[MeasMode::Nondemolition, MeasMode::Demolition];
+const CGATE_CODES: &[CGateKind] = &[CGateKind::Not, CGateKind::Copy, CGateKind::Swap];
+
+fn encode<T: PartialEq>(codes: &[T], item: &T) -> u8 {
+    codes
+        .iter()
+        .position(|code| code == item)
+        .expect("every kind has a binary code") as u8
+}
+
+fn decode<T: Copy>(codes: &[T], code: u8, what: &str) -> Result<T, String> {
+    codes
+        .get(code as usize)
+        .copied()
+        .ok_or_else(|| format!("unknown {} code {}", what, code))
+}
+
+impl From<&Instruction> for BinInst {
+    fn from(inst: &Instruction) -> Self {
+        match inst.clone() {
+            Instruction::Gate { kind, qbs, ctrls } => Self::Gate {
+                code: encode(GATE_CODES, &kind),
+                qbs,
+                ctrls,
+            },
+            Instruction::Meas { qb, cb, mode } => Self::Meas {
+                qb,
+                cb,
+                mode: encode(MEAS_CODES, &mode),
+            },
+            Instruction::CInit(cb) => Self::CInit { cb },
+            Instruction::CFree { cb, flag } => Self::CFree { cb, flag },
+            Instruction::CGate { kind, cbs, ctrls } => Self::CGate {
+                code: encode(CGATE_CODES, &kind),
+                cbs,
+                ctrls,
+            },
+            Instruction::Out { cb, name, elem } => Self::Out { cb, name, elem },
+        }
+    }
+}
+
+impl TryFrom<BinInst> for Instruction {
+    type Error = String;
+
+    fn try_from(inst: BinInst) -> Result<Self, String> {
+        Ok(match inst {
+            BinInst::Gate { code, qbs, ctrls } => Self::Gate {
+                kind: decode(GATE_CODES, code, "gate")?,
+                qbs,
+                ctrls,
+            },
+            BinInst::Meas { qb, cb, mode } => Self::Meas {
+                qb,
+                cb,
+                mode: decode(MEAS_CODES, mode, "measurement mode")?,
+            },
+            BinInst::CInit { cb } => Self::CInit(cb),
+            BinInst::CFree { cb, flag } => Self::CFree { cb, flag },
+            BinInst::CGate { code, cbs, ctrls } => Self::CGate {
+                kind: decode(CGATE_CODES, code, "classical gate")?,
+                cbs,
+                ctrls,
+            },
+            BinInst::Out { cb, name, elem } => Self::Out { cb, name, elem },
+        })
+    }
+}
+
+/// A qubit allocation or free, as encoded in a `Transcript`
+#[derive(Serialize, Deserialize)]
+enum BinAlloc {
+    Init(usize),
+    Free(usize, bool),
+}
+
+impl From<Alloc> for BinAlloc {
+    fn from(alloc: Alloc) -> Self {
+        match alloc {
+            Alloc::Init(qb) => Self::Init(qb),
+            Alloc::Free(qb, flag) => Self::Free(qb, flag),
+        }
+    }
+}
+
+impl From<BinAlloc> for Alloc {
+    fn from(alloc: BinAlloc) -> Self {
+        match alloc {
+            BinAlloc::Init(qb) => Self::Init(qb),
+            BinAlloc::Free(qb, flag) => Self::Free(qb, flag),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CircuitSchema {
+    version: u32,
+    n_qubits: usize,
+    instructions: Vec<InstSchema>,
+}
+
+/// Only the version, which is checked before trying to read anything else
+#[derive(Deserialize)]
+struct VersionSchema {
+    version: u32,
+}
+
+fn invalid(err: impl std::fmt::Display) -> PyErr {
+    PyValueError::new_err(format!("invalid circuit JSON: {}", err))
+}
+
+fn invalid_bytes(err: impl std::fmt::Display) -> PyErr {
+    PyValueError::new_err(format!("invalid circuit bytes: {}", err))
+}
+
+/// Check that deserialized instructions could have come from a compiled
+/// circuit, so that nothing downstream has to.
+fn validate(insts: &[Instruction], n_qubits: usize) -> Result<(), String> {
+    for (i, inst) in insts.iter().enumerate() {
+        validate_inst(i, inst, n_qubits)?;
+    }
+    Ok(())
+}
+
+fn validate_inst(i: usize, inst: &Instruction, n_qubits: usize) -> Result<(), String> {
+    if let Instruction::Gate { kind, qbs, .. } = inst {
+        if qbs.len() != kind.arity() {
+            return Err(format!(
+                "instruction {}: {} gate has {} qubits, not {}",
+                i,
+                kind.name(),
+                qbs.len(),
+                kind.arity()
+            ));
+        }
+    }
+    if let Instruction::CGate { kind, cbs, .. } = inst {
+        if cbs.len() != kind.arity() {
+            return Err(format!(
+                "instruction {}: {} classical gate has {} bits, not {}",
+                i,
+                kind.name(),
+                cbs.len(),
+                kind.arity()
+            ));
+        }
+    }
+    if let Some(qb) = inst.qubits().find(|&qb| qb >= n_qubits) {
+        return Err(format!(
+            "instruction {}: qubit {} out of range for {} qubits",
+            i, qb, n_qubits
+        ));
+    }
+    // A control can't also be a target, nor any operand appear twice
+    if let Some(qb) = repeated(inst.qubits()) {
+        return Err(format!(
+            "instruction {}: qubit {} used more than once",
+            i, qb
+        ));
+    }
+    if let Instruction::CGate { cbs, ctrls, .. } = inst {
+        if let Some(cb) = repeated(cbs.iter().chain(ctrls).copied()) {
+            return Err(format!("instruction {}: bit {} used more than once", i, cb));
+        }
+    }
+    Ok(())
+}
+
+/// The first item that has already come up, if any did
+fn repeated(mut items: impl Iterator<Item = usize>) -> Option<usize> {
+    let mut seen = HashSet::new();
+    items.find(|&item| !seen.insert(item))
+}
+
+pub(crate) fn to_json(insts: &[Instruction], n_qubits: usize) -> PyResult<String> {
+    let circ = CircuitSchema {
+        version: VERSION,
+        n_qubits,
+        instructions: insts.iter().map(InstSchema::from).collect(),
+    };
+    serde_json::to_string(&circ).map_err(invalid)
+}
+
+pub(crate) fn from_json(json: &str) -> PyResult<(Vec<Instruction>, usize)> {
+    let VersionSchema { version } = serde_json::from_str(json).map_err(invalid)?;
+    if version != VERSION {
+        return Err(invalid(format!(
+            "unsupported schema version {} (expected {})",
+            version, VERSION
+        )));
+    }
+    let circ: CircuitSchema = serde_json::from_str(json).map_err(invalid)?;
+    let insts: Vec<_> = circ
+        .instructions
+        .into_iter()
+        .map(Instruction::from)
+        .collect();
+    validate(&insts, circ.n_qubits).map_err(invalid)?;
+    Ok((insts, circ.n_qubits))
+}
+
+/// Variable-length integers, since qubit indices are almost always small
+fn bincode_options() -> impl bincode::Options {
+    bincode::DefaultOptions::new()
+}
+
+/// Errors reading and writing files are raised as Python's own `OSError`s, but
+/// running out of input means the input itself is bad.
+fn binary_error(err: bincode::ErrorKind) -> PyErr {
+    match err {
+        bincode::ErrorKind::Io(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+            invalid_bytes("unexpected end of input")
+        }
+        bincode::ErrorKind::Io(err) => err.into(),
+        err => invalid_bytes(err),
+    }
+}
+
+/// The length of the `MAGIC` bytes and format version
+const HEADER_LEN: usize = 8;
+
+/// The last bytes of every file written by `save`
+const INDEX_MAGIC: &[u8; 8] = b"CAVYINDX";
+
+/// The length of the index offset and `INDEX_MAGIC` at the end of a file
+const TRAILER_LEN: usize = 16;
+
+/// Encode the instructions one at a time, exactly as `bincode` would encode
+/// the tuple of `n_qubits` and a slice of them, but without first building
+/// every `BinInst` at once. Returns the offset of the first instruction.
+fn write_binary(mut out: impl Write, insts: &[Instruction], n_qubits: usize) -> PyResult<u64> {
+    out.write_all(MAGIC)?;
+    out.write_all(&BINARY_VERSION.to_le_bytes())?;
+    let prefix = (n_qubits, insts.len());
+    bincode_options()
+        .serialize_into(&mut out, &prefix)
+        .map_err(|err| binary_error(*err))?;
+    for inst in insts {
+        bincode_options()
+            .serialize_into(&mut out, &BinInst::from(inst))
+            .map_err(|err| binary_error(*err))?;
+    }
+    let prefix_len = bincode_options()
+        .serialized_size(&prefix)
+        .map_err(|err| binary_error(*err))?;
+    Ok(HEADER_LEN as u64 + prefix_len)
+}
+
+/// Check the `MAGIC` bytes and format version
+fn check_header(header: &[u8]) -> PyResult<()> {
+    if header.len() < HEADER_LEN || &header[..4] != MAGIC {
+        return Err(invalid_bytes("not a serialized circuit"));
+    }
+    let version = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+    if version != BINARY_VERSION {
+        return Err(invalid_bytes(format!(
+            "unsupported format version {} (expected {})",
+            version, BINARY_VERSION
+        )));
+    }
+    Ok(())
+}
+
+/// Read a circuit, ignoring anything after it, such as the index of a file
+fn read_binary(mut input: impl Read) -> PyResult<(Vec<Instruction>, usize)> {
+    let mut header = [0; HEADER_LEN];
+    match input.read_exact(&mut header) {
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+            return Err(invalid_bytes("not a serialized circuit"))
+        }
+        result => result?,
+    }
+    check_header(&header)?;
+    let (n_qubits, insts): (usize, Vec<BinInst>) = bincode_options()
+        .deserialize_from(input)
+        .map_err(|err| binary_error(*err))?;
+    let insts = insts
+        .into_iter()
+        .map(Instruction::try_from)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(invalid_bytes)?;
+    validate(&insts, n_qubits).map_err(invalid_bytes)?;
+    Ok((insts, n_qubits))
+}
+
+pub(crate) fn to_bytes(insts: &[Instruction], n_qubits: usize) -> PyResult<Vec<u8>> {
+    let mut bytes = Vec::new();
+    write_binary(&mut bytes, insts, n_qubits)?;
+    Ok(bytes)
+}
+
+pub(crate) fn from_bytes(bytes: &[u8]) -> PyResult<(Vec<Instruction>, usize)> {
+    read_binary(bytes)
+}
+
+pub(crate) fn transcript_to_bytes(transcript: &Transcript) -> PyResult<Vec<u8>> {
+    let mut bytes = to_bytes(&transcript.insts, transcript.n_qubits)?;
+    let allocs: Vec<_> = transcript
+        .allocs
+        .iter()
+        .map(|&(index, alloc)| (index, BinAlloc::from(alloc)))
+        .collect();
+    bincode_options()
+        .serialize_into(&mut bytes, &allocs)
+        .map_err(|err| binary_error(*err))?;
+    Ok(bytes)
+}
+
+pub(crate) fn transcript_from_bytes(mut bytes: &[u8]) -> PyResult<Transcript> {
+    let (insts, n_qubits) = read_binary(&mut bytes)?;
+    let allocs: Vec<(usize, BinAlloc)> = bincode_options()
+        .deserialize(bytes)
+        .map_err(|err| binary_error(*err))?;
+    let allocs: Vec<_> = allocs
+        .into_iter()
+        .map(|(index, alloc)| (index, Alloc::from(alloc)))
+        .collect();
+    let in_range = |&(index, alloc): &(usize, Alloc)| {
+        let (Alloc::Init(qb) | Alloc::Free(qb, _)) = alloc;
+        index <= insts.len() && qb < n_qubits
+    };
+    if !allocs.iter().all(in_range) {
+        return Err(invalid_bytes("allocation out of range"));
+    }
+    Ok(Transcript {
+        insts,
+        n_qubits,
+        allocs,
+    })
+}
+
+pub(crate) fn save(path: &Path, insts: &[Instruction], n_qubits: usize) -> PyResult<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    let mut offset = write_binary(&mut out, insts, n_qubits)?;
+    for inst in insts {
+        out.write_all(&offset.to_le_bytes())?;
+        offset += bincode_options()
+            .serialized_size(&BinInst::from(inst))
+            .map_err(|err| binary_error(*err))?;
+    }
+    // Having written every instruction, the offset is now that of the index.
+    out.write_all(&offset.to_le_bytes())?;
+    out.write_all(INDEX_MAGIC)?;
+    out.flush()?;
+    Ok(())
+}
+
+pub(crate) fn load(path: &Path) -> PyResult<(Vec<Instruction>, usize)> {
+    read_binary(BufReader::new(File::open(path)?))
+}
+
+/// A file written by `save`, from which instructions are decoded only when
+/// they're asked for
+pub(crate) struct Mapped {
+    bytes: Mmap,
+    n_qubits: usize,
+    /// The offset of the index, which also ends the last instruction
+    index: usize,
+    len: usize,
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    let mut word = [0; 8];
+    word.copy_from_slice(&bytes[offset..offset + 8]);
+    u64::from_le_bytes(word)
+}
+
+impl Mapped {
+    pub(crate) fn open(path: &Path) -> PyResult<Self> {
+        let bytes = Mmap::open(&File::open(path)?)?;
+        check_header(&bytes)?;
+        if bytes.len() < HEADER_LEN + TRAILER_LEN
+            || &bytes[bytes.len() - INDEX_MAGIC.len()..] != INDEX_MAGIC
+        {
+            return Err(invalid_bytes(
+                "no instruction index; only files written by `save` can be mapped",
+            ));
+        }
+        let trailer = bytes.len() - TRAILER_LEN;
+        let index = read_u64(&bytes, trailer) as usize;
+        if index < HEADER_LEN || index > trailer {
+            return Err(invalid_bytes("corrupt instruction index"));
+        }
+        let (n_qubits, len): (usize, usize) = bincode_options()
+            .allow_trailing_bytes()
+            .deserialize(&bytes[HEADER_LEN..index])
+            .map_err(|err| binary_error(*err))?;
+        if len.checked_mul(8) != Some(trailer - index) {
+            return Err(invalid_bytes("corrupt instruction index"));
+        }
+        Ok(Self {
+            bytes,
+            n_qubits,
+            index,
+            len,
+        })
+    }
+
+    pub(crate) fn n_qubits(&self) -> usize {
+        self.n_qubits
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Decode the instruction at `i`, which must be in bounds
+    pub(crate) fn get(&self, i: usize) -> PyResult<Instruction> {
+        let offset = read_u64(&self.bytes, self.index + 8 * i) as usize;
+        if offset < HEADER_LEN || offset >= self.index {
+            return Err(invalid_bytes("corrupt instruction index"));
+        }
+        let inst: BinInst = bincode_options()
+            .allow_trailing_bytes()
+            .deserialize(&self.bytes[offset..self.index])
+            .map_err(|err| binary_error(*err))?;
+        let inst = Instruction::try_from(inst).map_err(invalid_bytes)?;
+        validate_inst(i, &inst, self.n_qubits).map_err(invalid_bytes)?;
+        Ok(inst)
+    }
+}
+
+#[cfg(unix)]
+use mmap::Mmap;
+
+/// A read-only memory mapping of a whole file. Being private only keeps our
+/// view from being written through: pages not yet read still come from the
+/// file, so if it's truncated, touching them raises `SIGBUS`.
+#[cfg(unix)]
+mod mmap {
+    use std::{fs::File, io, ops::Deref, os::unix::io::AsRawFd, ptr, slice};
+
+    pub(crate) struct Mmap {
+        ptr: *mut libc::c_void,
+        len: usize,
+    }
+
+    // The mapping is read-only, and owned by nothing else.
+    unsafe impl Send for Mmap {}
+    unsafe impl Sync for Mmap {}
+
+    impl Mmap {
+        pub(crate) fn open(file: &File) -> io::Result<Self> {
+            let len = file.metadata()?.len() as usize;
+            if len == 0 {
+                // `mmap` refuses empty mappings.
+                return Ok(Self {
+                    ptr: ptr::null_mut(),
+                    len,
+                });
+            }
+            let ptr = unsafe {
+                libc::mmap(
+                    ptr::null_mut(),
+                    len,
+                    libc::PROT_READ,
+                    libc::MAP_PRIVATE,
+                    file.as_raw_fd(),
+                    0,
+                )
+            };
+            if ptr == libc::MAP_FAILED {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self { ptr, len })
+        }
+    }
+
+    impl Deref for Mmap {
+        type Target = [u8];
+
+        fn deref(&self) -> &[u8] {
+            if self.len == 0 {
+                return &[];
+            }
+            unsafe { slice::from_raw_parts(self.ptr as *const u8, self.len) }
+        }
+    }
+
+    impl Drop for Mmap {
+        fn drop(&mut self) {
+            if self.len > 0 {
+                unsafe {
+                    libc::munmap(self.ptr, self.len);
+                }
+            }
+        }
+    }
+}
+
+/// Elsewhere, the file is read in full instead.
+#[cfg(not(unix))]
+struct Mmap(Vec<u8>);
+
+#[cfg(not(unix))]
+impl Mmap {
+    fn open(mut file: &File) -> io::Result<Self> {
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        Ok(Self(bytes))
+    }
+}
+
+#[cfg(not(unix))]
+impl std::ops::Deref for Mmap {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
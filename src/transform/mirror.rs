@@ -0,0 +1,59 @@
+//! Mirror circuits, for benchmarking how faithfully hardware runs a circuit
+
+use pyo3::prelude::*;
+
+use super::{Pauli, Rng};
+use crate::{
+    circuit::Instruction,
+    export::check_all,
+    gates::{GateKind, MeasMode},
+};
+
+/// The inverse of a circuit made only of gates
+pub(crate) fn inverse(insts: &[Instruction]) -> PyResult<Vec<Instruction>> {
+    check_all(insts, "invert the circuit", |inst| {
+        matches!(inst, Instruction::Gate { .. })
+    })?;
+    let inverse = insts
+        .iter()
+        .rev()
+        .map(|inst| match inst {
+            Instruction::Gate { kind, qbs, ctrls } => {
+                let kind = match kind {
+                    GateKind::T => GateKind::TDag,
+                    GateKind::TDag => GateKind::T,
+                    kind => *kind,
+                };
+                Instruction::Gate {
+                    kind,
+                    qbs: qbs.clone(),
+                    ctrls: ctrls.clone(),
+                }
+            }
+            _ => unreachable!(),
+        })
+        .collect();
+    Ok(inverse)
+}
+
+/// The circuit, its inverse, a random Pauli layer, and a measurement of every
+/// qubit into the classical bit of the same index. Starting from all zeros,
+/// the outcome is exactly the X part of the Pauli layer, which is returned as a
+/// string of `'0'`s and `'1'`s, beginning with qubit 0.
+pub(crate) fn mirror(
+    insts: &[Instruction],
+    n_qubits: usize,
+    mode: MeasMode,
+    rng: &mut Rng,
+) -> PyResult<(Vec<Instruction>, String)> {
+    let mut out = insts.to_vec();
+    out.extend(inverse(insts)?);
+    let mut expected = String::with_capacity(n_qubits);
+    for qb in 0..n_qubits {
+        let pauli = Pauli::random(rng);
+        pauli.write(qb, &mut out);
+        expected.push(if pauli.x { '1' } else { '0' });
+    }
+    out.extend((0..n_qubits).map(|qb| Instruction::Meas { qb, cb: qb, mode }));
+    Ok((out, expected))
+}
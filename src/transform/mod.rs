@@ -0,0 +1,67 @@
+//! Transformations of compiled circuits into new circuits
+
+pub(crate) mod mirror;
+pub(crate) mod prune;
+pub(crate) mod restrict;
+pub(crate) mod sequence;
+pub(crate) mod twirl;
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{circuit::Instruction, gates::GateKind};
+
+/// A small, seedable random number generator (SplitMix64) for randomized
+/// transformations, which need to be reproducible but not cryptographic
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    /// Seeded from the clock if no seed is given
+    pub(crate) fn new(seed: Option<u64>) -> Self {
+        let seed = seed.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|time| time.as_nanos() as u64)
+                .unwrap_or_default()
+        });
+        Self(seed)
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+/// A single-qubit Pauli, up to phase, as its X and Z parts
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Pauli {
+    pub(crate) x: bool,
+    pub(crate) z: bool,
+}
+
+impl Pauli {
+    pub(crate) fn random(rng: &mut Rng) -> Self {
+        let bits = rng.next_u64();
+        Self {
+            x: bits & 1 != 0,
+            z: bits & 2 != 0,
+        }
+    }
+
+    /// Append the gates applying this Pauli to a qubit; there's no Y gate, so
+    /// Y is written as Z followed by X.
+    pub(crate) fn write(self, qb: usize, out: &mut Vec<Instruction>) {
+        for &(part, kind) in &[(self.z, GateKind::Z), (self.x, GateKind::X)] {
+            if part {
+                out.push(Instruction::Gate {
+                    kind,
+                    qbs: vec![qb],
+                    ctrls: vec![],
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,105 @@
+//! Elimination of the gates that can't affect a set of observables
+
+use std::collections::{HashMap, HashSet};
+
+use pyo3::{exceptions::PyValueError, prelude::*};
+
+use crate::{circuit::Instruction, gates::GateKind};
+
+/// What the rest of the circuit, after some point, does with a qubit
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Use {
+    /// It's measured in the Z basis, and at most acted on by diagonal gates
+    /// before that
+    Diagonal,
+    /// Anything else that affects the observables
+    General,
+}
+
+fn is_diagonal(kind: GateKind) -> bool {
+    matches!(kind, GateKind::Z | GateKind::T | GateKind::TDag)
+}
+
+/// Remove every gate that has no effect on the Z-basis measurement statistics
+/// of the `observables` at the end of the circuit, walking backwards from the
+/// end. Besides the gates outside their light cone, this removes diagonal
+/// gates on qubits that are only measured afterwards, and the measurements of
+/// qubits that no longer affect the observables. Classical instructions are
+/// kept.
+pub(crate) fn prune_for(
+    insts: &[Instruction],
+    n_qubits: usize,
+    observables: &[usize],
+) -> PyResult<Vec<Instruction>> {
+    check_qubits(n_qubits, observables)?;
+    let mut live: HashMap<usize, Use> = observables.iter().map(|&qb| (qb, Use::Diagonal)).collect();
+
+    let mut kept = Vec::with_capacity(insts.len());
+    for inst in insts.iter().rev() {
+        match inst {
+            Instruction::Gate { kind, .. } => {
+                let uses: Vec<_> = inst.qubits().map(|qb| live.get(&qb).copied()).collect();
+                if uses.iter().all(Option::is_none) {
+                    continue;
+                }
+                if is_diagonal(*kind) {
+                    // A diagonal gate commutes with everything after it up to
+                    // the measurements, which then can't see it
+                    if !uses.contains(&Some(Use::General)) {
+                        continue;
+                    }
+                    for qb in inst.qubits() {
+                        live.entry(qb).or_insert(Use::Diagonal);
+                    }
+                } else {
+                    for qb in inst.qubits() {
+                        live.insert(qb, Use::General);
+                    }
+                }
+            }
+            // A measurement is diagonal, too, but not unitary, so it stays if
+            // its qubit matters
+            Instruction::Meas { qb, .. } if !live.contains_key(qb) => continue,
+            _ => {}
+        }
+        kept.push(inst.clone());
+    }
+    kept.reverse();
+    Ok(kept)
+}
+
+/// Remove every gate and measurement outside the causal past of the `qubits`
+/// at the end of the circuit: walking backwards from the end, an instruction
+/// is kept if it acts on a qubit in the light cone, which then grows to all of
+/// the qubits it acts on. Unlike `prune_for`, this keeps every gate in the
+/// light cone, diagonal or not. Classical instructions are kept.
+pub(crate) fn light_cone(
+    insts: &[Instruction],
+    n_qubits: usize,
+    qubits: &[usize],
+) -> PyResult<Vec<Instruction>> {
+    check_qubits(n_qubits, qubits)?;
+    let mut cone: HashSet<usize> = qubits.iter().copied().collect();
+    let mut kept = Vec::with_capacity(insts.len());
+    for inst in insts.iter().rev() {
+        if let Instruction::Gate { .. } | Instruction::Meas { .. } = inst {
+            if !inst.qubits().any(|qb| cone.contains(&qb)) {
+                continue;
+            }
+            cone.extend(inst.qubits());
+        }
+        kept.push(inst.clone());
+    }
+    kept.reverse();
+    Ok(kept)
+}
+
+fn check_qubits(n_qubits: usize, qubits: &[usize]) -> PyResult<()> {
+    match qubits.iter().find(|&&qb| qb >= n_qubits) {
+        Some(qb) => {
+            let msg = format!("qubit {} out of range for {} qubits", qb, n_qubits);
+            Err(PyValueError::new_err(msg))
+        }
+        None => Ok(()),
+    }
+}
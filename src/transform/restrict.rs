@@ -0,0 +1,42 @@
+//! Restriction of circuits to a subset of their qubits
+
+use pyo3::{exceptions::PyValueError, prelude::*};
+
+use crate::{circuit::Instruction, export::reject};
+
+/// The instructions acting only on `qubits`, with classical instructions kept
+/// and qubits keeping their indices. An instruction that acts on both the
+/// qubits and others crosses the cut: if `cut`, it's dropped, and its index
+/// returned with the others dropped; otherwise, any such instruction is an
+/// error.
+pub(crate) fn restrict(
+    insts: &[Instruction],
+    n_qubits: usize,
+    qubits: &[usize],
+    cut: bool,
+) -> PyResult<(Vec<Instruction>, Vec<usize>)> {
+    let mut kept = vec![false; n_qubits];
+    for &qb in qubits {
+        if qb >= n_qubits {
+            let msg = format!("qubit {} out of range for {} qubits", qb, n_qubits);
+            return Err(PyValueError::new_err(msg));
+        }
+        kept[qb] = true;
+    }
+
+    let mut restricted = vec![];
+    let mut crossing = vec![];
+    for (i, inst) in insts.iter().enumerate() {
+        let inside = inst.qubits().filter(|&qb| kept[qb]).count();
+        if inside == inst.qubits().count() {
+            restricted.push(inst.clone());
+        } else if inside > 0 {
+            crossing.push((i, inst));
+        }
+    }
+    if !cut {
+        reject("restrict the circuit without cutting", crossing)?;
+        return Ok((restricted, vec![]));
+    }
+    Ok((restricted, crossing.into_iter().map(|(i, _)| i).collect()))
+}
@@ -0,0 +1,52 @@
+//! Building longer sequences, as for benchmarking and echo experiments, out of
+//! a compiled kernel
+
+use pyo3::{exceptions::PyValueError, prelude::*};
+
+use crate::{circuit::Instruction, gates::GateKind};
+
+/// The kernel `n` times in a row, with `interleave` between each repetition
+pub(crate) fn repeat(
+    kernel: &[Instruction],
+    n: usize,
+    interleave: Option<&[Instruction]>,
+) -> Vec<Instruction> {
+    let interleave = interleave.unwrap_or(&[]);
+    let mut insts = Vec::with_capacity(n * (kernel.len() + interleave.len()));
+    for i in 0..n {
+        if i > 0 {
+            insts.extend_from_slice(interleave);
+        }
+        insts.extend_from_slice(kernel);
+    }
+    insts
+}
+
+/// The kernel, a layer of `idle_gate` on every qubit, the kernel again, and the
+/// layer again. The idle gate is its own inverse, so without noise this is the
+/// kernel followed by the kernel conjugated by the layer, which is the kernel
+/// twice over only if the kernel commutes with the layer.
+pub(crate) fn echo(
+    kernel: &[Instruction],
+    n_qubits: usize,
+    idle_gate: &str,
+) -> PyResult<Vec<Instruction>> {
+    let kind = match GateKind::from_name(idle_gate) {
+        Some(kind @ GateKind::X) | Some(kind @ GateKind::Z) | Some(kind @ GateKind::H) => kind,
+        _ => {
+            let msg = format!(
+                "idle gate must be one of 'X', 'Z', or 'H', not '{}'",
+                idle_gate
+            );
+            return Err(PyValueError::new_err(msg));
+        }
+    };
+    let layer: Vec<_> = (0..n_qubits)
+        .map(|qb| Instruction::Gate {
+            kind,
+            qbs: vec![qb],
+            ctrls: vec![],
+        })
+        .collect();
+    Ok([kernel, &layer, kernel, &layer].concat())
+}
@@ -0,0 +1,96 @@
+//! Randomized compiling: Pauli twirling of the two-qubit Clifford gates
+
+use super::{Pauli, Rng};
+use crate::{circuit::Instruction, export::FlatGate, gates::GateKind};
+
+/// The emitted circuit so far is the ideal circuit followed by the Pauli
+/// `frame`, which is carried forward through Clifford gates rather than
+/// corrected immediately, and only written out when a gate it can't be carried
+/// through is reached.
+struct Twirler<'r> {
+    rng: &'r mut Rng,
+    frame: Vec<Pauli>,
+    out: Vec<Instruction>,
+}
+
+impl<'r> Twirler<'r> {
+    /// Write out and clear the frame on a qubit
+    fn flush(&mut self, qb: usize) {
+        let pauli = std::mem::take(&mut self.frame[qb]);
+        pauli.write(qb, &mut self.out);
+    }
+
+    fn push(&mut self, inst: &Instruction) {
+        let (kind, qbs, ctrls) = match inst {
+            Instruction::Gate { kind, qbs, ctrls } => (*kind, qbs, ctrls),
+            // Measurement absorbs the Z part of the frame; and afterwards, the
+            // qubit is in a Z eigenstate, so its frame is trivial
+            Instruction::Meas { qb, .. } => {
+                self.frame[*qb].z = false;
+                self.flush(*qb);
+                self.out.push(inst.clone());
+                return;
+            }
+            _ => {
+                self.out.push(inst.clone());
+                return;
+            }
+        };
+
+        let gate = FlatGate::new(kind, qbs, ctrls);
+        match (gate.kind, &gate.ctrls[..], gate.tgts) {
+            // Paulis commute with the frame, up to a phase
+            (GateKind::X, [], _) | (GateKind::Z, [], _) => {}
+            (GateKind::H, [], &[qb]) => {
+                let pauli = &mut self.frame[qb];
+                std::mem::swap(&mut pauli.x, &mut pauli.z);
+            }
+            (GateKind::X, &[ctrl], &[tgt]) => {
+                self.twirl(ctrl, tgt);
+                self.frame[tgt].x ^= self.frame[ctrl].x;
+                self.frame[ctrl].z ^= self.frame[tgt].z;
+            }
+            (GateKind::Z, &[ctrl], &[tgt]) => {
+                self.twirl(ctrl, tgt);
+                self.frame[tgt].z ^= self.frame[ctrl].x;
+                self.frame[ctrl].z ^= self.frame[tgt].x;
+            }
+            (GateKind::SWAP, [], &[fst, snd]) => {
+                self.twirl(fst, snd);
+                self.frame.swap(fst, snd);
+            }
+            _ => {
+                for qb in inst.qubits() {
+                    self.flush(qb);
+                }
+            }
+        }
+        self.out.push(inst.clone());
+    }
+
+    /// Write a random Pauli before a two-qubit gate, and fold it into the frame
+    fn twirl(&mut self, fst: usize, snd: usize) {
+        for &qb in &[fst, snd] {
+            let pauli = Pauli::random(self.rng);
+            pauli.write(qb, &mut self.out);
+            self.frame[qb].x ^= pauli.x;
+            self.frame[qb].z ^= pauli.z;
+        }
+    }
+}
+
+/// One randomized instance of the circuit, logically equivalent to it
+pub(crate) fn twirl(insts: &[Instruction], n_qubits: usize, rng: &mut Rng) -> Vec<Instruction> {
+    let mut twirler = Twirler {
+        rng,
+        frame: vec![Pauli::default(); n_qubits],
+        out: Vec::with_capacity(insts.len()),
+    };
+    for inst in insts {
+        twirler.push(inst);
+    }
+    for qb in 0..n_qubits {
+        twirler.flush(qb);
+    }
+    twirler.out
+}
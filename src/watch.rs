@@ -0,0 +1,165 @@
+//! Recompiling source files whenever they change. A thread polls the files'
+//! metadata without holding the GIL, and takes it only to compile a changed
+//! file and call back into Python with the result.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime},
+};
+
+use pyo3::{exceptions::PyValueError, prelude::*, types::PyString};
+
+use crate::{decode_source, CavyError, Session};
+
+/// How long the watching thread sleeps at a time, so that it notices being
+/// stopped soon even with a long polling interval
+const TICK: Duration = Duration::from_millis(20);
+
+/// What identifies a version of a file: its modification time and size
+type Stamp = (Option<SystemTime>, u64);
+
+/// A running `Session.watch`, which recompiles its files until it's stopped or
+/// its callback raises an exception
+#[pyclass]
+pub(crate) struct Watcher {
+    stopped: Arc<AtomicBool>,
+    thread: Mutex<Option<JoinHandle<PyResult<()>>>>,
+}
+
+impl Watcher {
+    pub(crate) fn start(
+        session: Py<Session>,
+        path: PathBuf,
+        on_compile: PyObject,
+        interval: f64,
+        suffix: String,
+    ) -> PyResult<Self> {
+        if !(interval > 0.0 && interval.is_finite()) {
+            let msg = format!(
+                "interval must be a positive number of seconds, not {}",
+                interval
+            );
+            return Err(PyValueError::new_err(msg));
+        }
+        if !path.exists() {
+            let msg = format!(
+                "cannot watch '{}': no such file or directory",
+                path.display()
+            );
+            return Err(PyErr::new::<CavyError, _>(msg));
+        }
+        let interval = Duration::from_secs_f64(interval);
+        let stopped = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&stopped);
+        let thread = thread::spawn(move || {
+            let mut stamps: HashMap<PathBuf, Stamp> = HashMap::new();
+            // The files are always compiled once, even if watching is stopped
+            // straight away
+            loop {
+                let mut current = HashMap::new();
+                for file in watched_files(&path, &suffix) {
+                    let stamp = match fs::metadata(&file) {
+                        Ok(meta) => (meta.modified().ok(), meta.len()),
+                        // It was deleted since it was listed
+                        Err(_) => continue,
+                    };
+                    if stamps.get(&file) != Some(&stamp) {
+                        Python::with_gil(|py| compile(py, &session, &file, &on_compile))?;
+                    }
+                    current.insert(file, stamp);
+                }
+                stamps = current;
+                let start = Instant::now();
+                while start.elapsed() < interval && !flag.load(Ordering::SeqCst) {
+                    thread::sleep(TICK.min(interval));
+                }
+                if flag.load(Ordering::SeqCst) {
+                    return Ok(());
+                }
+            }
+        });
+        Ok(Self {
+            stopped,
+            thread: Mutex::new(Some(thread)),
+        })
+    }
+}
+
+#[pymethods]
+impl Watcher {
+    /// Stop watching, waiting for any compilation or callback in progress to
+    /// finish. If watching had already stopped because the callback or a
+    /// hook raised an exception, that exception is raised here.
+    fn stop(&self, py: Python) -> PyResult<()> {
+        self.stopped.store(true, Ordering::SeqCst);
+        let thread = self.thread.lock().unwrap().take();
+        match thread {
+            Some(thread) => py
+                .allow_threads(|| thread.join())
+                .unwrap_or_else(|_| Err(PyErr::new::<CavyError, _>("the watcher panicked"))),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether the file is still being watched
+    #[getter]
+    fn running(&self) -> bool {
+        match &*self.thread.lock().unwrap() {
+            Some(thread) => !thread.is_finished(),
+            None => false,
+        }
+    }
+}
+
+/// A watcher that's no longer referenced stops at its next poll.
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+}
+
+/// The path itself, if it's a file, or else the files directly in it whose
+/// names end with `suffix`, in order
+fn watched_files(path: &Path, suffix: &str) -> Vec<PathBuf> {
+    if !path.is_dir() {
+        return vec![path.to_owned()];
+    }
+    let mut files: Vec<_> = fs::read_dir(path)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|file| file.is_file() && file.to_string_lossy().ends_with(suffix))
+        .collect();
+    files.sort();
+    files
+}
+
+/// Compile a file as `Session.compile` would, and call back with its path and
+/// the result: the circuit, or the `CavyError` that compiling it raised
+fn compile(py: Python, session: &Py<Session>, file: &Path, on_compile: &PyObject) -> PyResult<()> {
+    let src = match fs::read(file) {
+        Ok(bytes) => decode_source(&bytes),
+        Err(err) => {
+            let msg = format!("could not read '{}': {}", file.display(), err);
+            Err(PyErr::new::<CavyError, _>(msg))
+        }
+    };
+    let circ = src.and_then(|src| {
+        let src = PyString::new(py, &src);
+        Session::compile(session.borrow(py), py, src, None, None, false)
+    });
+    let result = match circ {
+        Ok(circ) => circ.into_py(py),
+        Err(err) if err.is_instance::<CavyError>(py) => err.instance(py).into_py(py),
+        Err(err) => return Err(err),
+    };
+    on_compile.call1(py, (file.to_string_lossy().into_owned(), result))?;
+    Ok(())
+}
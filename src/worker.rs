@@ -0,0 +1,247 @@
+//! Running the compiler on threads of its own, so that waiting for it can be
+//! interrupted: by a timeout, by a signal, or by abandoning it from another
+//! thread. The compiler itself can't be stopped partway through, so an
+//! interrupted compilation still runs to completion on its thread, using CPU
+//! and memory all the while, and its result is discarded.
+
+use std::{
+    mem,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use pyo3::{exceptions::PyValueError, prelude::*};
+
+use cavy::{
+    cavy_errors::ErrorBuf,
+    circuit::CircuitBuf,
+    context::Context,
+    session::{Config, Statistics},
+    util::FmtWith,
+};
+
+use crate::{
+    circuit::Circuit, hooks::Outcome, CavyAbandonedError, CavyError, CavyTimeoutError, Session,
+};
+
+/// How often to check for signals while waiting for the compiler
+const SIGNAL_POLL: Duration = Duration::from_millis(50);
+
+/// The compiled circuit, if any, or the formatted compiler errors
+pub(crate) type CompileResult = Result<Option<CircuitBuf>, String>;
+
+pub(crate) fn parse_timeout(timeout: Option<f64>) -> PyResult<Option<Duration>> {
+    match timeout {
+        Some(secs) if !(secs > 0.0 && secs.is_finite()) => {
+            let msg = format!("timeout must be a positive number of seconds, not {}", secs);
+            Err(PyValueError::new_err(msg))
+        }
+        timeout => Ok(timeout.map(Duration::from_secs_f64)),
+    }
+}
+
+fn compile_inner(ctx: &mut Context, src: String) -> Result<Option<CircuitBuf>, ErrorBuf> {
+    let id = ctx.srcs.insert_input(&src);
+    cavy::compile::compile_circuit(id, ctx)
+}
+
+/// Compile source on the current thread
+pub(crate) fn compile(conf: &Config, src: String) -> CompileResult {
+    let mut stats = Statistics::new();
+    let mut ctx = Context::new(conf, &mut stats);
+    compile_inner(&mut ctx, src).map_err(|errs| errs.fmt_with(&ctx).to_string())
+}
+
+/// Start compiling, or whatever other job, on a new thread
+pub(crate) fn spawn<T, F>(job: F) -> Mutex<Receiver<T>>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        // Nobody is listening anymore if the compilation was interrupted
+        let _ = tx.send(job());
+    });
+    Mutex::new(rx)
+}
+
+/// Wait for a compilation without holding the GIL, waking up now and then to
+/// check for signals, so that a long compilation can be interrupted with
+/// Ctrl-C. Fails only if waiting was interrupted or the compiler panicked; the
+/// compiler's own errors are in the result.
+pub(crate) fn wait<T: Send>(
+    py: Python,
+    rx: &Mutex<Receiver<T>>,
+    timeout: Option<Duration>,
+    abandoned: &AtomicBool,
+) -> PyResult<T> {
+    wait_with(
+        py,
+        |wait| rx.lock().unwrap().recv_timeout(wait),
+        timeout,
+        abandoned,
+    )
+}
+
+/// Wait as `wait` does, receiving with `recv`, which waits at most the given
+/// time. A result that's already there is returned even if waiting was
+/// abandoned.
+fn wait_with<T: Send>(
+    py: Python,
+    recv: impl Fn(Duration) -> Result<T, RecvTimeoutError> + Sync,
+    timeout: Option<Duration>,
+    abandoned: &AtomicBool,
+) -> PyResult<T> {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    loop {
+        let wait = match deadline {
+            _ if abandoned.load(Ordering::SeqCst) => Duration::ZERO,
+            Some(deadline) => deadline
+                .saturating_duration_since(Instant::now())
+                .min(SIGNAL_POLL),
+            None => SIGNAL_POLL,
+        };
+        match py.allow_threads(|| recv(wait)) {
+            Ok(circ) => return Ok(circ),
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(PyErr::new::<CavyError, _>("the compiler panicked"));
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+        }
+        if abandoned.load(Ordering::SeqCst) {
+            return Err(PyErr::new::<CavyAbandonedError, _>(
+                "compilation was abandoned",
+            ));
+        }
+        py.check_signals()?;
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            let msg = format!(
+                "compilation timed out after {} seconds, and is still running in the background",
+                timeout.unwrap().as_secs_f64()
+            );
+            return Err(PyErr::new::<CavyTimeoutError, _>(msg));
+        }
+    }
+}
+
+/// A compilation running in the background, returned by
+/// `Session.compile_background`, which can be waited for, or abandoned, from
+/// any thread.
+///
+/// Abandoning a compilation does not stop it. The compiler can't be
+/// interrupted, so its thread keeps running, holding its CPU time and memory,
+/// until the compilation finishes on its own, and only then exits; a program
+/// that never finishes compiling keeps its thread for the life of the process.
+///
+/// `cancel` is another name for `abandon`, and `Session.compile_cancellable`
+/// for `Session.compile_background`, though neither cancels the compiler.
+#[pyclass]
+pub(crate) struct CompileHandle {
+    state: Mutex<HandleState>,
+    /// The full source, for the `after` hooks
+    src: String,
+    abandoned: AtomicBool,
+    session: Py<Session>,
+    intern: bool,
+}
+
+/// How far a background compilation has got. Whichever waiting thread
+/// receives the result leaves it here, so that the others find it instead of
+/// a disconnected channel.
+enum HandleState {
+    Running(Receiver<(CompileResult, Duration)>),
+    Received((CompileResult, Duration)),
+    Done(Result<Option<Py<Circuit>>, String>),
+}
+
+impl CompileHandle {
+    pub(crate) fn new(
+        rx: Mutex<Receiver<(CompileResult, Duration)>>,
+        src: String,
+        session: Py<Session>,
+        intern: bool,
+    ) -> Self {
+        Self {
+            state: Mutex::new(HandleState::Running(rx.into_inner().unwrap())),
+            src,
+            abandoned: AtomicBool::new(false),
+            session,
+            intern,
+        }
+    }
+
+    /// Wait at most `wait` for the result to be received, by this thread or
+    /// any other
+    fn recv(&self, wait: Duration) -> Result<(), RecvTimeoutError> {
+        let mut state = self.state.lock().unwrap();
+        if let HandleState::Running(rx) = &*state {
+            *state = HandleState::Received(rx.recv_timeout(wait)?);
+        }
+        Ok(())
+    }
+}
+
+#[pymethods]
+impl CompileHandle {
+    /// Stop waiting for the compilation: `result`, including any call already
+    /// waiting in another thread, raises a `CavyAbandonedError`, unless the
+    /// result had already been received. The compilation itself runs on to
+    /// completion in the background.
+    fn abandon(&self) {
+        self.abandoned.store(true, Ordering::SeqCst);
+    }
+
+    /// The same as `abandon`
+    fn cancel(&self) {
+        self.abandon();
+    }
+
+    /// Wait for the compiled circuit, as `Session.compile` would return it, for
+    /// at most `timeout` seconds if given. A timeout raises a
+    /// `CavyTimeoutError`, but leaves the compilation running, so `result` can
+    /// be called again. Any number of threads can wait at once, and all of
+    /// them get the same result, except that an exception raised by an
+    /// `after` hook is raised only in the thread that ran it.
+    #[args(timeout = "None")]
+    fn result(&self, py: Python, timeout: Option<f64>) -> PyResult<Option<Py<Circuit>>> {
+        let timeout = parse_timeout(timeout)?;
+        wait_with(py, |wait| self.recv(wait), timeout, &self.abandoned)?;
+        // Nobody holds the lock for long once the result is in, and the
+        // circuit is built while holding the GIL, so only one thread builds it
+        let mut state = self.state.lock().unwrap();
+        let placeholder = HandleState::Done(Ok(None));
+        let (outcome, elapsed) = match mem::replace(&mut *state, placeholder) {
+            HandleState::Received((Ok(circ), elapsed)) => {
+                let circ = circ.map(|circ| {
+                    let session = self.session.clone_ref(py);
+                    Py::new(py, Circuit::new(py, circ, &self.src, session, self.intern))
+                });
+                (
+                    circ.transpose().map_err(|err| err.to_string()),
+                    Some(elapsed),
+                )
+            }
+            HandleState::Received((Err(errs), elapsed)) => (Err(errs), Some(elapsed)),
+            HandleState::Done(outcome) => (outcome, None),
+            HandleState::Running(_) => unreachable!("the result was received"),
+        };
+        *state = HandleState::Done(outcome.clone());
+        // A hook may well wait for this same handle
+        drop(state);
+        let circ = outcome.map_err(PyErr::new::<CavyError, _>);
+        if let Some(elapsed) = elapsed {
+            let outcome = Outcome::of(py, elapsed, &circ);
+            self.session
+                .borrow(py)
+                .hooks
+                .after(py, &self.src, outcome)?;
+        }
+        circ
+    }
+}
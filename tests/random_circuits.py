@@ -0,0 +1,53 @@
+# Random circuits for the tests, built through `Circuit.from_json` so that
+# they can use every kind of gate, whatever the compiler would emit.
+
+import json
+import random
+
+from pycavy import Circuit
+
+N_QUBITS = 3
+
+# Gates as (name, number of target qubits, number of controls), covering the
+# Cliffords through which twirling carries its Pauli frame, the gates it must
+# flush the frame before, and the diagonal gates that pruning treats specially
+_GATES = [
+    ('H', 1, 0),
+    ('X', 1, 0),
+    ('Z', 1, 0),
+    ('T', 1, 0),
+    ('TDag', 1, 0),
+    ('CX', 2, 0),
+    ('SWAP', 2, 0),
+    ('X', 1, 1),
+    ('Z', 1, 1),
+    ('H', 1, 1),
+    ('CX', 2, 1),
+    ('SWAP', 2, 1),
+]
+
+
+def random_circuit(
+    rng: random.Random, length: int, measure: bool = True
+) -> Circuit:
+    insts = []
+    n_cbits = 0
+    for _ in range(length):
+        if measure and rng.random() < 0.1:
+            qb = rng.randrange(N_QUBITS)
+            insts.append({'kind': 'meas', 'qb': qb, 'cb': n_cbits})
+            n_cbits += 1
+            continue
+        gate, n_tgts, n_ctrls = rng.choice(_GATES)
+        qbs = rng.sample(range(N_QUBITS), n_tgts + n_ctrls)
+        insts.append({
+            'kind': 'gate',
+            'gate': gate,
+            'qbs': qbs[:n_tgts],
+            'ctrls': qbs[n_tgts:],
+        })
+    return Circuit.from_json(json.dumps({
+        'version': 1,
+        'n_qubits': N_QUBITS,
+        'instructions': insts,
+    }))
@@ -0,0 +1,107 @@
+# A small statevector simulator for the tests, which checks transformed
+# circuits against the originals. It runs circuits in the JSON schema of
+# `Circuit.to_json`, so that the tests don't depend on any one export format,
+# and needs nothing beyond the standard library.
+
+import cmath
+import json
+import math
+from typing import Dict, List, Tuple
+
+_R = 1 / math.sqrt(2)
+_T = cmath.exp(1j * math.pi / 4)
+
+# The single-qubit gates, as ((a, b), (c, d)) matrices
+_MATRICES = {
+    'H': ((_R, _R), (_R, -_R)),
+    'X': ((0, 1), (1, 0)),
+    'Z': ((1, 0), (0, -1)),
+    'T': ((1, 0), (0, _T)),
+    'TDag': ((1, 0), (0, _T.conjugate())),
+}
+
+# A run of a circuit is a list of branches, one for each sequence of
+# measurement outcomes, holding its probability and the normalized state at
+# the end of the circuit.
+Branches = Dict[Tuple[int, ...], Tuple[float, List[complex]]]
+
+
+def _controlled(index: int, ctrls) -> bool:
+    return all(index >> c & 1 for c in ctrls)
+
+
+def _apply_gate(state: List[complex], gate: str, qbs, ctrls) -> None:
+    if gate == 'CX':
+        gate, qbs, ctrls = 'X', qbs[1:], list(ctrls) + [qbs[0]]
+    if gate == 'SWAP':
+        fst, snd = qbs
+        for i in range(len(state)):
+            if (i >> fst & 1) and not (i >> snd & 1) and _controlled(i, ctrls):
+                j = i ^ (1 << fst) ^ (1 << snd)
+                state[i], state[j] = state[j], state[i]
+        return
+    ((a, b), (c, d)) = _MATRICES[gate]
+    (qb,) = qbs
+    for i in range(len(state)):
+        if not (i >> qb & 1) and _controlled(i, ctrls):
+            j = i | 1 << qb
+            zero, one = state[i], state[j]
+            state[i] = a * zero + b * one
+            state[j] = c * zero + d * one
+
+
+def _measure(
+    state: List[complex], qb: int
+) -> List[Tuple[int, float, List[complex]]]:
+    """Project onto each outcome with nonzero probability"""
+    outcomes = []
+    for bit in (0, 1):
+        projected = [
+            amp if (i >> qb & 1) == bit else 0 for i, amp in enumerate(state)
+        ]
+        prob = sum(abs(amp) ** 2 for amp in projected)
+        if prob > 1e-12:
+            norm = math.sqrt(prob)
+            outcomes.append((bit, prob, [amp / norm for amp in projected]))
+    return outcomes
+
+
+def run(circ) -> Branches:
+    """Simulate a compiled circuit from the all-zeros state. Measurements are
+    nondemolition; classical instructions have no effect on the state.
+    """
+    circ = json.loads(circ.to_json())
+    state = [0j] * (1 << circ['n_qubits'])
+    state[0] = 1
+    branches = [((), 1.0, state)]
+    for inst in circ['instructions']:
+        if inst['kind'] == 'gate':
+            for _, _, state in branches:
+                _apply_gate(state, inst['gate'], inst['qbs'], inst['ctrls'])
+        elif inst['kind'] == 'meas':
+            branches = [
+                (record + (bit,), prob * p, projected)
+                for record, prob, state in branches
+                for bit, p, projected in _measure(state, inst['qb'])
+            ]
+    return {record: (prob, state) for record, prob, state in branches}
+
+
+def equal_up_to_phase(
+    a: List[complex], b: List[complex], tol: float = 1e-9
+) -> bool:
+    """Whether two normalized states differ by at most a global phase"""
+    overlap = sum(x.conjugate() * y for x, y in zip(a, b))
+    return abs(abs(overlap) - 1) < tol
+
+
+def marginals(branches: Branches, qubits) -> Dict[Tuple[int, ...], float]:
+    """The probabilities of the outcomes of measuring `qubits` in the Z basis
+    at the end of the circuit, over all its branches
+    """
+    probs: Dict[Tuple[int, ...], float] = {}
+    for prob, state in branches.values():
+        for i, amp in enumerate(state):
+            key = tuple(i >> qb & 1 for qb in qubits)
+            probs[key] = probs.get(key, 0) + prob * abs(amp) ** 2
+    return probs
@@ -0,0 +1,65 @@
+# Checks indexing and slicing circuits, which behave like lists of their
+# instructions. Run with `python -m unittest discover tests` once pycavy is
+# built and installed.
+
+import os
+import random
+import tempfile
+import unittest
+
+from pycavy import Circuit
+
+from random_circuits import random_circuit
+
+SLICES = [
+    slice(None),
+    slice(2, 7),
+    slice(-5, None),
+    slice(None, -3),
+    slice(1, None, 3),
+    slice(None, None, -1),
+    slice(-2, 3, -2),
+    slice(8, 2),
+    slice(100, 200),
+]
+
+
+class TestIndexing(unittest.TestCase):
+    def setUp(self):
+        self.circ = random_circuit(random.Random(0), 12)
+        self.insts = list(self.circ)
+
+    def test_integer_indices(self):
+        for i in range(-len(self.insts), len(self.insts)):
+            self.assertEqual(repr(self.circ[i]), repr(self.insts[i]))
+        for i in (len(self.insts), -len(self.insts) - 1):
+            with self.assertRaises(IndexError):
+                self.circ[i]
+        with self.assertRaises(TypeError):
+            self.circ['0']
+
+    def test_slices(self):
+        for key in SLICES:
+            sliced = self.circ[key]
+            self.assertIsInstance(sliced, Circuit)
+            self.assertEqual(sliced.n_qubits, self.circ.n_qubits)
+            self.assertEqual(
+                [repr(inst) for inst in sliced],
+                [repr(inst) for inst in self.insts[key]],
+            )
+
+    def test_mapped_slices(self):
+        with tempfile.TemporaryDirectory() as tmp:
+            path = os.path.join(tmp, 'circuit.cavy')
+            self.circ.save(path)
+            mapped = Circuit.load(path, mmap=True)
+            for key in SLICES:
+                self.assertEqual(
+                    mapped[key].to_json(), self.circ[key].to_json()
+                )
+            self.assertEqual(repr(mapped[-1]), repr(self.insts[-1]))
+            del mapped
+
+
+if __name__ == '__main__':
+    unittest.main()
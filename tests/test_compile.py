@@ -0,0 +1,200 @@
+# Checks the ways of compiling a program besides `Session.compile`: in the
+# background, where any number of threads may wait for the result, in a child
+# process, and on every change to a watched file; and that every one of them
+# runs a session's hooks. Run with `python -m unittest discover tests` once
+# pycavy is built and installed.
+
+import os
+import queue
+import tempfile
+import threading
+import unittest
+
+from pycavy import CavyError, Circuit, Session
+
+PROGRAM = 'let x = ?false;\nlet y = ?false;\n'
+BAD_PROGRAM = 'let x = ;\n'
+
+
+class TestBackground(unittest.TestCase):
+    def wait_in_threads(self, handle, n_threads: int):
+        barrier = threading.Barrier(n_threads)
+        results = [None] * n_threads
+
+        def wait(i: int):
+            barrier.wait()
+            try:
+                results[i] = handle.result(timeout=10)
+            except Exception as err:
+                results[i] = err
+
+        threads = [
+            threading.Thread(target=wait, args=(i,)) for i in range(n_threads)
+        ]
+        for thread in threads:
+            thread.start()
+        for thread in threads:
+            thread.join()
+        return results
+
+    def test_many_waiters_get_the_same_circuit(self):
+        session = Session()
+        for _ in range(20):
+            handle = session.compile_background(PROGRAM)
+            circ, *rest = self.wait_in_threads(handle, 4)
+            self.assertIsInstance(circ, Circuit)
+            for other in rest:
+                self.assertIs(other, circ)
+            self.assertIs(handle.result(), circ)
+
+    def test_result_survives_cancellation(self):
+        handle = Session().compile_cancellable(PROGRAM)
+        circ = handle.result()
+        handle.cancel()
+        self.assertIs(handle.result(), circ)
+
+
+
+@unittest.skipUnless(hasattr(os, 'fork'), 'isolation needs fork')
+class TestIsolated(unittest.TestCase):
+    def test_same_circuit_as_in_process(self):
+        expected = Session().compile(PROGRAM)
+        circ = Session().compile(PROGRAM, isolated=True)
+        self.assertEqual(circ.to_json(), expected.to_json())
+        self.assertEqual(circ.lifetimes(), expected.lifetimes())
+        self.assertEqual(
+            [repr(inst) for inst in circ.instructions()],
+            [repr(inst) for inst in expected.instructions()],
+        )
+
+    def test_compiler_errors(self):
+        with self.assertRaises(CavyError):
+            Session().compile(BAD_PROGRAM, isolated=True)
+
+    def test_no_on_instruction(self):
+        with self.assertRaises(ValueError):
+            Session().compile(PROGRAM, isolated=True, on_instruction=print)
+
+
+class TestWatch(unittest.TestCase):
+    def setUp(self):
+        tmp = tempfile.TemporaryDirectory()
+        self.addCleanup(tmp.cleanup)
+        self.dir = tmp.name
+        self.results = queue.Queue()
+
+    def write(self, name: str, src: str):
+        path = os.path.join(self.dir, name)
+        with open(path, 'w') as f:
+            f.write(src)
+        return path
+
+    def on_compile(self, path, result):
+        self.results.put((os.path.basename(path), result))
+
+    def next_results(self, n: int):
+        return sorted(
+            (self.results.get(timeout=10) for _ in range(n)),
+            key=lambda result: result[0],
+        )
+
+    def test_recompiles_changed_files(self):
+        self.write('a.cav', PROGRAM)
+        self.write('b.cav', PROGRAM)
+        self.write('notes.txt', BAD_PROGRAM)
+        watcher = Session().watch(
+            self.dir, on_compile=self.on_compile, interval=0.01
+        )
+        try:
+            (a, circ_a), (b, circ_b) = self.next_results(2)
+            self.assertEqual((a, b), ('a.cav', 'b.cav'))
+            self.assertIsInstance(circ_a, Circuit)
+            self.assertEqual(circ_a.to_json(), circ_b.to_json())
+
+            self.write('b.cav', BAD_PROGRAM)
+            ((b, err),) = self.next_results(1)
+            self.assertEqual(b, 'b.cav')
+            self.assertIsInstance(err, CavyError)
+            self.assertTrue(watcher.running)
+        finally:
+            watcher.stop()
+        self.assertFalse(watcher.running)
+        self.assertTrue(self.results.empty())
+
+    def test_callback_errors_stop_watching(self):
+        path = self.write('a.cav', PROGRAM)
+
+        def on_compile(path, result):
+            raise KeyError(path)
+
+        watcher = Session().watch(path, on_compile=on_compile, interval=0.01)
+        with self.assertRaises(KeyError):
+            watcher.stop()
+
+    def test_missing_path(self):
+        with self.assertRaises(CavyError):
+            Session().watch(
+                os.path.join(self.dir, 'missing.cav'),
+                on_compile=self.on_compile,
+            )
+
+
+class TestHooks(unittest.TestCase):
+    def setUp(self):
+        self.session = Session()
+        self.before = []
+        self.after = []
+        self.session.add_hook(
+            before=self.before.append, after=self.after.append
+        )
+
+    def assert_reported(self, circ, n_qubits, instructions):
+        self.assertEqual(len(self.before), 1)
+        (info,) = self.after
+        self.assertEqual(info['source'], PROGRAM)
+        self.assertGreaterEqual(info['elapsed'], 0)
+        self.assertIs(info['circuit'], circ)
+        self.assertEqual(info['n_qubits'], n_qubits)
+        self.assertEqual(info['instructions'], instructions)
+        self.assertIsNone(info['error'])
+
+    def test_compile(self):
+        circ = self.session.compile(PROGRAM)
+        self.assert_reported(circ, 2, 2)
+
+    def test_compile_iter(self):
+        gates = list(self.session.compile_iter(PROGRAM))
+        self.assertEqual(len(gates), 2)
+        self.assert_reported(None, None, None)
+
+    def test_compile_background(self):
+        handle = self.session.compile_background(PROGRAM)
+        circ = handle.result()
+        self.assertIs(handle.result(), circ)
+        self.assert_reported(circ, 2, 2)
+
+    def test_compile_many(self):
+        (circ,) = self.session.compile_many([PROGRAM])
+        self.assert_reported(circ, 2, 2)
+
+    def test_errors_are_reported(self):
+        compilers = [
+            self.session.compile,
+            self.session.compile_iter,
+            lambda src: self.session.compile_background(src).result(),
+        ]
+        for compile in compilers:
+            self.after.clear()
+            with self.assertRaises(CavyError):
+                compile(BAD_PROGRAM)
+            (info,) = self.after
+            self.assertIsInstance(info['error'], CavyError)
+            self.assertIsNone(info['circuit'])
+        self.after.clear()
+        (err,) = self.session.compile_many([BAD_PROGRAM])
+        (info,) = self.after
+        self.assertIs(info['error'], err)
+
+
+if __name__ == '__main__':
+    unittest.main()
@@ -0,0 +1,197 @@
+# Checks the exports of circuits to other formats, and that what each one
+# can't represent is rejected up front. Run with
+# `python -m unittest discover tests` once pycavy is built and installed.
+
+import json
+import os
+import re
+import tempfile
+import unittest
+
+from pycavy import CavyError, Circuit, Session
+
+TARGETS = [
+    'qasm2', 'qasm3', 'qcec', 'schedule_json', 'experiment_script', 'qir',
+    'quil', 'stim', 'qiskit', 'cirq', 'tket', 'braket', 'qulacs',
+]
+
+
+def circuit(n_qubits, *insts):
+    return Circuit.from_json(json.dumps({
+        'version': 1,
+        'n_qubits': n_qubits,
+        'instructions': list(insts),
+    }))
+
+
+def gate(name, qbs, ctrls=()):
+    return {'kind': 'gate', 'gate': name, 'qbs': qbs, 'ctrls': list(ctrls)}
+
+
+def meas(qb, cb):
+    return {'kind': 'meas', 'qb': qb, 'cb': cb}
+
+
+class TestCheckTarget(unittest.TestCase):
+    def test_clifford_circuit_fits_every_target(self):
+        circ = circuit(2, gate('H', [0]), gate('CX', [0, 1]), meas(1, 0))
+        for target in TARGETS:
+            circ.check_target(target)
+
+    def test_same_error_as_the_export(self):
+        circ = circuit(2, gate('H', [0]), gate('T', [1]), gate('T', [0]))
+        with self.assertRaises(CavyError) as checked:
+            circ.check_target('stim')
+        with self.assertRaises(CavyError) as exported:
+            circ.to_stim()
+        self.assertEqual(str(checked.exception), str(exported.exception))
+        self.assertIn('1: ', str(checked.exception))
+        self.assertIn('2: ', str(checked.exception))
+
+    def test_classical_gates(self):
+        circ = circuit(
+            1,
+            meas(0, 0),
+            {'kind': 'c_gate', 'op': 'not', 'cbs': [0], 'ctrls': []},
+        )
+        circ.check_target('qasm3')
+        for target in ('qasm2', 'quil', 'qiskit', 'braket', 'qulacs'):
+            with self.assertRaises(CavyError):
+                circ.check_target(target)
+
+    def test_braket_measures_each_qubit_once_at_the_end(self):
+        circ = circuit(
+            2,
+            meas(0, 0),
+            gate('H', [1]),
+            gate('CX', [0, 1]),
+            meas(1, 1),
+            meas(1, 2),
+        )
+        with self.assertRaises(CavyError) as checked:
+            circ.check_target('braket')
+        lines = str(checked.exception).splitlines()[1:]
+        self.assertEqual([line.split(':')[0] for line in lines], ['2', '4'])
+        circuit(2, gate('H', [0]), meas(0, 0), meas(1, 1)).check_target(
+            'braket'
+        )
+
+    def test_unknown_target(self):
+        with self.assertRaisesRegex(ValueError, 'qasm2, qasm3'):
+            circuit(1).check_target('ionq')
+
+
+class TestQir(unittest.TestCase):
+    def attributes(self, qir):
+        (line,) = [
+            line for line in qir.splitlines()
+            if line.startswith('attributes #0')
+        ]
+        return dict(re.findall(r'"(\w+)"="(\w+)"', line))
+
+    def test_final_measurements_are_base_profile(self):
+        circ = circuit(2, gate('H', [0]), meas(0, 0), meas(1, 1))
+        qir = circ.to_qir(timestamp=False)
+        self.assertEqual(self.attributes(qir)['qir_profiles'], 'base_profile')
+        for flag in ('qir_major_version', 'qir_minor_version',
+                     'dynamic_qubit_management', 'dynamic_result_management'):
+            self.assertIn('!"{}"'.format(flag), qir)
+
+    def test_reuse_needs_the_adaptive_profile(self):
+        reused_qubit = circuit(1, meas(0, 0), gate('H', [0]), meas(0, 1))
+        reused_result = circuit(2, meas(0, 0), meas(1, 0))
+        for circ in (reused_qubit, reused_result):
+            attributes = self.attributes(circ.to_qir(timestamp=False))
+            self.assertEqual(attributes['qir_profiles'], 'adaptive_profile')
+
+    def test_records_only_measured_bits(self):
+        circ = circuit(
+            1,
+            {'kind': 'c_init', 'cb': 0},
+            meas(0, 3),
+            {'kind': 'out', 'cb': 5, 'name': 'x', 'elem': 0},
+        )
+        qir = circ.to_qir(timestamp=False)
+        self.assertEqual(self.attributes(qir)['required_num_results'], '1')
+        record = 'call void @__quantum__rt__result_record_output'
+        self.assertEqual(qir.count(record), 1)
+        self.assertIn('mz__body(%Qubit* null, %Result* null)', qir)
+
+
+class TestHeader(unittest.TestCase):
+    PROGRAM = 'let x = ?false;\n'
+
+    def exports(self, circ, **kwargs):
+        return {
+            '//': [circ.to_qasm2(**kwargs), circ.to_qasm3(**kwargs)],
+            '#': [circ.to_quil(**kwargs), circ.to_stim(**kwargs)],
+            ';': [circ.to_qir(**kwargs)],
+        }
+
+    def header(self, text, comment):
+        lines = text.splitlines()
+        header = {}
+        field = re.compile(re.escape(comment) + r' (\w+): (\S+)$')
+        for line in lines[1:]:
+            match = field.match(line)
+            if not match:
+                break
+            header[match[1]] = match[2]
+        self.assertTrue(lines[0].startswith(comment + ' pycavy '))
+        return header
+
+    def test_compiled_circuit(self):
+        circ = Session().compile(self.PROGRAM)
+        for comment, texts in self.exports(circ).items():
+            for text in texts:
+                header = self.header(text, comment)
+                self.assertEqual(
+                    header.keys(), {'config', 'source', 'generated'}
+                )
+                self.assertRegex(header['config'], '^[0-9a-f]{16}$')
+                self.assertRegex(
+                    header['generated'],
+                    r'^\d{4}-\d\d-\d\dT\d\d:\d\d:\d\dZ$',
+                )
+
+    def test_fingerprints(self):
+        first = Session().compile(self.PROGRAM).to_qasm2(timestamp=False)
+        again = Session().compile(self.PROGRAM).to_qasm2(timestamp=False)
+        self.assertEqual(first, again)
+        other_source = Session().compile(self.PROGRAM + '\n')
+        other_config = Session(opt_level=0).compile(self.PROGRAM)
+        header = self.header(first, '//')
+        for circ, changed in ((other_source, 'source'),
+                              (other_config, 'config')):
+            other = self.header(circ.to_qasm2(timestamp=False), '//')
+            for key in ('config', 'source'):
+                self.assertEqual(
+                    header[key] != other[key], key == changed, key
+                )
+
+    def test_deserialized_circuit(self):
+        circ = circuit(1, gate('H', [0]), meas(0, 0))
+        for comment, texts in self.exports(circ, timestamp=False).items():
+            for text in texts:
+                self.assertEqual(self.header(text, comment), {})
+        with tempfile.TemporaryDirectory() as tmp:
+            path = os.path.join(tmp, 'circuit.bin')
+            circ.save(path)
+            mapped = Circuit.load(path, mmap=True)
+            qasm_path = os.path.join(tmp, 'circuit.qasm')
+            mapped.write_qasm2(qasm_path, timestamp=False)
+            with open(qasm_path) as f:
+                self.assertEqual(f.read(), circ.to_qasm2(timestamp=False))
+
+    def test_header_precedes_the_program(self):
+        circ = circuit(1, gate('H', [0]))
+        qasm = circ.to_qasm2()
+        self.assertRegex(qasm, re.compile('^OPENQASM 2.0;$', re.M))
+        self.assertTrue(all(
+            line.startswith('//')
+            for line in qasm.split('OPENQASM')[0].splitlines()
+        ))
+
+
+if __name__ == '__main__':
+    unittest.main()
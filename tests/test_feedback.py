@@ -0,0 +1,95 @@
+# Checks the feedback graph of circuits, which follows measurement results
+# through the classical instructions that read them, and the map of their
+# outputs' bits. Run with `python -m unittest discover tests` once pycavy is
+# built and installed.
+
+import json
+import unittest
+
+from pycavy import Circuit
+
+
+def circuit(*insts):
+    return Circuit.from_json(json.dumps({
+        'version': 1,
+        'n_qubits': 2,
+        'instructions': list(insts),
+    }))
+
+
+def gate(name, qbs):
+    return {'kind': 'gate', 'gate': name, 'qbs': qbs, 'ctrls': []}
+
+
+def meas(qb, cb):
+    return {'kind': 'meas', 'qb': qb, 'cb': cb}
+
+
+def c_gate(op, cbs, ctrls=()):
+    return {'kind': 'c_gate', 'op': op, 'cbs': cbs, 'ctrls': list(ctrls)}
+
+
+def out(cb, name='r', elem=0):
+    return {'kind': 'out', 'cb': cb, 'name': name, 'elem': elem}
+
+
+class TestFeedbackGraph(unittest.TestCase):
+    def test_unitary_circuit(self):
+        circ = circuit(gate('H', [0]), gate('CX', [0, 1]))
+        self.assertEqual(circ.feedback_graph(), {})
+
+    def test_direct_and_transitive_dependencies(self):
+        circ = circuit(
+            meas(0, 0),                     # 0
+            meas(1, 1),                     # 1
+            c_gate('not', [2], ctrls=[0]),  # 2
+            c_gate('copy', [2, 3]),         # 3
+            c_gate('not', [1], ctrls=[3]),  # 4
+            out(1),                         # 5
+            out(4),                         # 6
+        )
+        self.assertEqual(circ.feedback_graph(), {
+            2: [0],
+            3: [0],
+            4: [0, 1],
+            5: [0, 1],
+        })
+
+    def test_swaps_exchange_dependencies(self):
+        circ = circuit(
+            meas(0, 0),
+            c_gate('swap', [0, 1]),
+            out(0),
+            out(1),
+        )
+        self.assertEqual(circ.feedback_graph(), {1: [0], 3: [0]})
+
+    def test_remeasuring_and_reinitializing(self):
+        circ = circuit(
+            meas(0, 0),
+            meas(0, 0),
+            out(0),
+            meas(1, 1),
+            {'kind': 'c_init', 'cb': 1},
+            c_gate('not', [0], ctrls=[1]),
+        )
+        self.assertEqual(circ.feedback_graph(), {2: [1], 5: [1]})
+
+
+class TestOutputs(unittest.TestCase):
+    def test_bits_in_element_order(self):
+        circ = circuit(
+            meas(0, 0),
+            meas(1, 1),
+            out(1, 'x', elem=1),
+            out(0, 'x', elem=0),
+            out(1, 'flag'),
+        )
+        self.assertEqual(circ.outputs(), {'x': [0, 1], 'flag': [1]})
+
+    def test_no_outputs(self):
+        self.assertEqual(circuit(meas(0, 0)).outputs(), {})
+
+
+if __name__ == '__main__':
+    unittest.main()
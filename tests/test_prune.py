@@ -0,0 +1,135 @@
+# Checks that pruning a circuit for some observables, or cutting it down to
+# their light cone, leaves the Z-basis measurement statistics of those
+# observables unchanged. Run with `python -m unittest discover tests` once
+# pycavy is built and installed.
+
+import json
+import random
+import unittest
+
+from pycavy import Circuit
+
+import statevector
+from random_circuits import N_QUBITS, random_circuit
+
+
+def circuit(n_qubits: int, insts) -> Circuit:
+    return Circuit.from_json(json.dumps({
+        'version': 1,
+        'n_qubits': n_qubits,
+        'instructions': insts,
+    }))
+
+
+def gate(name: str, *qbs, ctrls=()):
+    return {
+        'kind': 'gate',
+        'gate': name,
+        'qbs': list(qbs),
+        'ctrls': list(ctrls),
+    }
+
+
+class MarginalsTestCase(unittest.TestCase):
+    def assert_same_marginals(
+        self, circ: Circuit, pruned: Circuit, observables
+    ):
+        expected = statevector.marginals(statevector.run(circ), observables)
+        actual = statevector.marginals(statevector.run(pruned), observables)
+        for outcome in set(expected) | set(actual):
+            self.assertAlmostEqual(
+                expected.get(outcome, 0), actual.get(outcome, 0)
+            )
+
+
+class TestPruneFor(MarginalsTestCase):
+    def test_random_circuits(self):
+        rng = random.Random(0)
+        for _ in range(200):
+            circ = random_circuit(rng, 20)
+            n_observables = rng.randint(1, N_QUBITS - 1)
+            observables = rng.sample(range(N_QUBITS), n_observables)
+            pruned = circ.prune_for(observables)
+            self.assertLessEqual(len(pruned), len(circ))
+            self.assert_same_marginals(circ, pruned, observables)
+
+    def test_removes_gates_outside_light_cone(self):
+        circ = circuit(2, [gate('H', 0), gate('H', 1), gate('T', 1)])
+        self.assertEqual(len(circ.prune_for([0])), 1)
+
+    def test_removes_trailing_diagonal_gates(self):
+        circ = circuit(2, [
+            gate('H', 0),
+            gate('CX', 0, 1),
+            gate('T', 0),
+            gate('Z', 1, ctrls=[0]),
+        ])
+        pruned = circ.prune_for([0, 1])
+        self.assertEqual(len(pruned), 2)
+        self.assert_same_marginals(circ, pruned, [0, 1])
+
+    def test_keeps_diagonal_gates_before_interference(self):
+        circ = circuit(1, [
+            gate('H', 0),
+            gate('T', 0),
+            gate('T', 0),
+            gate('H', 0),
+        ])
+        self.assertEqual(len(circ.prune_for([0])), 4)
+
+    def test_keeps_classical_instructions(self):
+        circ = circuit(2, [
+            gate('H', 1),
+            {'kind': 'meas', 'qb': 1, 'cb': 0},
+            {'kind': 'out', 'cb': 0, 'name': 'x', 'elem': 0},
+        ])
+        pruned = json.loads(circ.prune_for([0]).to_json())['instructions']
+        self.assertEqual([inst['kind'] for inst in pruned], ['out'])
+
+    def test_rejects_out_of_range_observables(self):
+        with self.assertRaises(ValueError):
+            circuit(1, [gate('H', 0)]).prune_for([1])
+
+
+class TestLightCone(MarginalsTestCase):
+    def test_random_circuits(self):
+        rng = random.Random(1)
+        for _ in range(200):
+            circ = random_circuit(rng, 20)
+            n_qubits = rng.randint(1, N_QUBITS - 1)
+            qubits = rng.sample(range(N_QUBITS), n_qubits)
+            cone = circ.light_cone(qubits)
+            self.assertLessEqual(len(circ.prune_for(qubits)), len(cone))
+            self.assertLessEqual(len(cone), len(circ))
+            self.assert_same_marginals(circ, cone, qubits)
+
+    def test_grows_through_multi_qubit_gates(self):
+        circ = circuit(3, [
+            gate('H', 1),
+            gate('H', 2),
+            gate('CX', 1, 0),
+            gate('X', 2, ctrls=[1]),
+        ])
+        cone = json.loads(circ.light_cone([0]).to_json())['instructions']
+        self.assertEqual(
+            [(inst['gate'], inst['qbs']) for inst in cone],
+            [('H', [1]), ('CX', [1, 0])],
+        )
+
+    def test_keeps_diagonal_gates(self):
+        circ = circuit(2, [
+            gate('H', 0),
+            gate('CX', 0, 1),
+            gate('T', 0),
+            gate('Z', 1, ctrls=[0]),
+        ])
+        self.assertEqual(len(circ.light_cone([0, 1])), 4)
+        self.assertEqual(len(circ.light_cone([0])), 4)
+
+    def test_rejects_out_of_range_qubits(self):
+        with self.assertRaises(ValueError):
+            circuit(1, [gate('H', 0)]).light_cone([1])
+
+
+if __name__ == '__main__':
+    unittest.main()
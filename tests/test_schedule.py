@@ -0,0 +1,106 @@
+# Checks the moment schedule and the timing attached to it. Run with
+# `python -m unittest discover tests` once pycavy is built and installed.
+
+import json
+import unittest
+
+from pycavy import Circuit
+
+
+def gate(name, qbs, ctrls=()):
+    return {'kind': 'gate', 'gate': name, 'qbs': qbs, 'ctrls': list(ctrls)}
+
+
+# A CX, a Toffoli, and a controlled Hadamard, one after the other on qubit 1
+CONTROLLED = Circuit.from_json(json.dumps({
+    'version': 1,
+    'n_qubits': 3,
+    'instructions': [
+        gate('CX', [0, 1]),
+        gate('X', [1], ctrls=[0, 2]),
+        gate('H', [2], ctrls=[1]),
+        {'kind': 'meas', 'qb': 2, 'cb': 0},
+    ],
+}))
+
+DURATIONS = {'cx': 300, 'ccx': 900, 'ch': 400, 'measure': 1000}
+
+
+def op(name, qubits, ctrls, duration):
+    channels = ['q{}'.format(q) for q in ctrls + qubits]
+    return {
+        'op': name,
+        'qubits': qubits,
+        'ctrls': ctrls,
+        'channels': channels,
+        'duration': duration,
+    }
+
+
+def moment(time, start, ops):
+    duration = max(op['duration'] for op in ops)
+    return {'time': time, 'start': start, 'duration': duration, 'ops': ops}
+
+
+# Every controlled gate lists its controls in `ctrls`, and only its targets in
+# `qubits`, whether or not the compiler wrote it as a CX
+SCHEDULE = {
+    'version': 1,
+    'n_qubits': 3,
+    'channels': ['q0', 'q1', 'q2'],
+    'n_moments': 4,
+    'unit': 'ns',
+    'duration': 2600,
+    'moments': [
+        moment(0, 0, [op('cx', [1], [0], 300)]),
+        moment(1, 300, [op('ccx', [1], [0, 2], 900)]),
+        moment(2, 1200, [op('ch', [2], [1], 400)]),
+        moment(3, 1600, [{
+            'op': 'measure',
+            'qubits': [2],
+            'cbit': 0,
+            'channels': ['q2'],
+            'duration': 1000,
+        }]),
+    ],
+}
+
+
+class TestSchedule(unittest.TestCase):
+    def test_schedule_json(self):
+        schedule = json.loads(CONTROLLED.to_schedule_json('ns', DURATIONS))
+        self.assertEqual(schedule, SCHEDULE)
+
+
+class TestTiming(unittest.TestCase):
+    def test_controlled_gates_have_their_own_durations(self):
+        runtime = CONTROLLED.estimated_runtime('ns', DURATIONS)
+        self.assertEqual(runtime, 300 + 900 + 400 + 1000)
+
+    def test_missing_durations_are_named(self):
+        with self.assertRaisesRegex(ValueError, 'ccx, ch'):
+            CONTROLLED.estimated_runtime('ns', {'cx': 300, 'measure': 1000})
+
+
+    def test_budget(self):
+        shot_time = (300 + 900 + 400 + 1000) * 1e-9
+        budget = CONTROLLED.budget(1000, 1e3, 'ns', DURATIONS)
+        self.assertAlmostEqual(budget['shot_time'], shot_time)
+        # Limited by the repetition rate, not the circuit
+        self.assertAlmostEqual(budget['total_time'], 1.0)
+        self.assertIsNone(budget['cost'])
+        budget = CONTROLLED.budget(
+            1000, 1e9, 'ns', DURATIONS, cost_per_shot=0.01, cost_per_task=0.3
+        )
+        self.assertAlmostEqual(budget['total_time'], 1000 * shot_time)
+        self.assertAlmostEqual(budget['cost'], 10.3)
+
+    def test_budget_needs_seconds(self):
+        with self.assertRaises(ValueError):
+            CONTROLLED.budget(1000, 1e3, 'dt', DURATIONS)
+        with self.assertRaises(ValueError):
+            CONTROLLED.budget(1000, 0, 'ns', DURATIONS)
+
+
+if __name__ == '__main__':
+    unittest.main()
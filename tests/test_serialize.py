@@ -0,0 +1,202 @@
+# Checks that circuits come back unchanged from each serialization format,
+# and that malformed input is rejected with a `ValueError` rather than read as
+# a circuit. Run with `python -m unittest discover tests` once pycavy is built
+# and installed.
+
+import json
+import os
+import random
+import struct
+import tempfile
+import unittest
+
+from pycavy import Circuit
+
+from random_circuits import random_circuit
+
+
+def circuit_json(n_qubits, *insts) -> str:
+    return json.dumps({
+        'version': 1,
+        'n_qubits': n_qubits,
+        'instructions': list(insts),
+    })
+
+
+def gate(name, qbs, ctrls=()):
+    return {'kind': 'gate', 'gate': name, 'qbs': qbs, 'ctrls': list(ctrls)}
+
+
+def c_gate(op, cbs, ctrls=()):
+    return {'kind': 'c_gate', 'op': op, 'cbs': cbs, 'ctrls': list(ctrls)}
+
+
+class TestFromJson(unittest.TestCase):
+    def assert_invalid(self, json_str: str, message: str):
+        with self.assertRaisesRegex(ValueError, message):
+            Circuit.from_json(json_str)
+
+    def test_control_equal_to_target(self):
+        self.assert_invalid(
+            circuit_json(2, gate('H', [0]), gate('X', [1], ctrls=[1])),
+            'instruction 1: qubit 1 used more than once',
+        )
+        self.assert_invalid(
+            circuit_json(3, gate('CX', [0, 1], ctrls=[0])),
+            'instruction 0: qubit 0 used more than once',
+        )
+
+    def test_duplicate_qubits(self):
+        self.assert_invalid(
+            circuit_json(2, gate('SWAP', [1, 1])),
+            'qubit 1 used more than once',
+        )
+        self.assert_invalid(
+            circuit_json(3, gate('Z', [0], ctrls=[2, 2])),
+            'qubit 2 used more than once',
+        )
+
+    def test_duplicate_bits(self):
+        self.assert_invalid(
+            circuit_json(1, c_gate('copy', [0, 0])),
+            'bit 0 used more than once',
+        )
+        self.assert_invalid(
+            circuit_json(1, c_gate('not', [3], ctrls=[3])),
+            'bit 3 used more than once',
+        )
+
+    def test_distinct_operands_are_accepted(self):
+        circ = Circuit.from_json(circuit_json(
+            3, gate('X', [2], ctrls=[0, 1]), c_gate('swap', [0, 1], [2])
+        ))
+        self.assertEqual(len(circ), 2)
+
+
+class TestBytes(unittest.TestCase):
+    def setUp(self):
+        self.circ = random_circuit(random.Random(0), 30)
+        self.bytes = self.circ.to_bytes()
+
+    def test_round_trip(self):
+        rng = random.Random(1)
+        for _ in range(20):
+            circ = random_circuit(rng, 50)
+            read = Circuit.from_bytes(circ.to_bytes())
+            self.assertEqual(read.to_json(), circ.to_json())
+        self.assertLess(len(self.bytes), len(self.circ.to_json()))
+
+    def test_header(self):
+        self.assertEqual(self.bytes[:4], b'CAVY')
+        self.assertEqual(struct.unpack('<I', self.bytes[4:8]), (1,))
+        with self.assertRaisesRegex(ValueError, 'not a serialized circuit'):
+            Circuit.from_bytes(b'JUNK' + self.bytes[4:])
+        newer = self.bytes[:4] + struct.pack('<I', 2) + self.bytes[8:]
+        with self.assertRaisesRegex(ValueError, 'format version 2'):
+            Circuit.from_bytes(newer)
+
+    def test_truncated(self):
+        for end in range(len(self.bytes)):
+            with self.assertRaises(ValueError, msg=end):
+                Circuit.from_bytes(self.bytes[:end])
+
+    def test_corrupt_instruction(self):
+        # The first instruction of a one-gate circuit, after the header and
+        # the qubit and instruction counts: the variant, then the gate code
+        circ = Circuit.from_json(circuit_json(1, gate('H', [0])))
+        data = bytearray(circ.to_bytes())
+        self.assertEqual(data[10:12], bytes([0, 0]))
+        data[11] = 200
+        with self.assertRaisesRegex(ValueError, 'unknown gate code 200'):
+            Circuit.from_bytes(bytes(data))
+
+
+class TestFiles(unittest.TestCase):
+    def setUp(self):
+        tmp = tempfile.TemporaryDirectory()
+        self.addCleanup(tmp.cleanup)
+        self.path = os.path.join(tmp.name, 'circuit.cavy')
+        self.circ = random_circuit(random.Random(2), 30)
+        self.circ.save(self.path)
+        with open(self.path, 'rb') as f:
+            self.data = f.read()
+
+    def write(self, data: bytes):
+        with open(self.path, 'wb') as f:
+            f.write(data)
+
+    def test_load(self):
+        expected = self.circ.to_json()
+        self.assertEqual(Circuit.load(self.path).to_json(), expected)
+        self.write(self.circ.to_bytes())
+        self.assertEqual(Circuit.load(self.path).to_json(), expected)
+
+    def test_trailer_index(self):
+        encoded = self.circ.to_bytes()
+        self.assertEqual(self.data[:len(encoded)], encoded)
+        self.assertEqual(self.data[-8:], b'CAVYINDX')
+        (index,) = struct.unpack('<Q', self.data[-16:-8])
+        self.assertEqual(index, len(encoded))
+        offsets = struct.unpack(
+            '<{}Q'.format(len(self.circ)), self.data[index:-16]
+        )
+        self.assertEqual(list(offsets), sorted(offsets))
+        # Each instruction decodes on its own from its offset
+        for i, offset in enumerate(offsets):
+            one = Circuit.from_json(circuit_json(self.circ.n_qubits))
+            prefix = one.to_bytes()[:-1] + bytes([1])
+            end = offsets[i + 1] if i + 1 < len(offsets) else index
+            read = Circuit.from_bytes(prefix + self.data[offset:end])
+            self.assertEqual(repr(read[0]), repr(self.circ[i]))
+
+    def test_mapped_circuit(self):
+        mapped = Circuit.load(self.path, mmap=True)
+        self.assertEqual(len(mapped), len(self.circ))
+        self.assertEqual(mapped.n_qubits, self.circ.n_qubits)
+        self.assertEqual(
+            [repr(inst) for inst in mapped],
+            [repr(inst) for inst in self.circ],
+        )
+        counts = {}
+        for inst in json.loads(self.circ.to_json())['instructions']:
+            name = inst['gate'] if inst['kind'] == 'gate' else 'Meas'
+            counts[name] = counts.get(name, 0) + 1
+        self.assertEqual(mapped.counts(), counts)
+
+    def test_mapping_needs_the_index(self):
+        self.write(self.circ.to_bytes())
+        with self.assertRaisesRegex(ValueError, 'no instruction index'):
+            Circuit.load(self.path, mmap=True)
+
+    def test_truncated(self):
+        encoded = len(self.circ.to_bytes())
+        for end in range(0, len(self.data), 7):
+            self.write(self.data[:end])
+            with self.assertRaises(ValueError, msg=end):
+                Circuit.load(self.path, mmap=True)
+            # Reading the file in full ignores the index
+            if end < encoded:
+                with self.assertRaises(ValueError, msg=end):
+                    Circuit.load(self.path)
+
+    def test_bad_index(self):
+        (index,) = struct.unpack('<Q', self.data[-16:-8])
+        for bad in (0, index + 1, len(self.data)):
+            self.write(
+                self.data[:-16] + struct.pack('<Q', bad) + self.data[-8:]
+            )
+            with self.assertRaisesRegex(ValueError, 'corrupt', msg=bad):
+                Circuit.load(self.path, mmap=True)
+
+    def test_bad_instruction_offset(self):
+        (index,) = struct.unpack('<Q', self.data[-16:-8])
+        bad = struct.pack('<Q', 2)
+        self.write(self.data[:index] + bad + self.data[index + 8:])
+        mapped = Circuit.load(self.path, mmap=True)
+        with self.assertRaisesRegex(ValueError, 'corrupt instruction index'):
+            mapped[0]
+        self.assertEqual(repr(mapped[1]), repr(self.circ[1]))
+
+
+if __name__ == '__main__':
+    unittest.main()
@@ -0,0 +1,53 @@
+# Checks compiling through a compile server in a subprocess, which should give
+# the same circuits as compiling in this process, and survive the server
+# dying. Run with `python -m unittest discover tests` once pycavy is built and
+# installed.
+
+import os
+import signal
+import socket
+import tempfile
+import unittest
+
+import pycavy
+from pycavy import CavyError, Session
+
+PROGRAM = 'let x = ?false;\nlet y = ?false;\n'
+BAD_PROGRAM = 'let x = ;\n'
+
+
+@unittest.skipUnless(hasattr(socket, 'AF_UNIX'), 'needs Unix sockets')
+class TestService(unittest.TestCase):
+    def setUp(self):
+        tmp = tempfile.TemporaryDirectory()
+        self.addCleanup(tmp.cleanup)
+        self.server = pycavy.serve(os.path.join(tmp.name, 'cavy.sock'))
+        self.addCleanup(self.server.close)
+
+    def test_same_circuit_as_in_process(self):
+        remote = self.server.session(opt_level=1).compile(PROGRAM)
+        local = Session(opt_level=1).compile(PROGRAM)
+        self.assertEqual(remote.to_json(), local.to_json())
+        self.assertIsNone(remote.session)
+
+    def test_compiler_errors(self):
+        with self.assertRaises(CavyError) as remote:
+            self.server.compile(BAD_PROGRAM)
+        with self.assertRaises(CavyError) as local:
+            Session().compile(BAD_PROGRAM)
+        self.assertEqual(str(remote.exception), str(local.exception))
+
+    def test_bad_options(self):
+        with self.assertRaises(ValueError):
+            self.server.compile(PROGRAM, no_such_option=True)
+
+    def test_restarts_after_a_crash(self):
+        self.server.compile(PROGRAM)
+        os.kill(self.server.pid, signal.SIGKILL)
+        with self.assertRaisesRegex(CavyError, 'signal 9'):
+            self.server.compile(PROGRAM)
+        self.assertIsNotNone(self.server.compile(PROGRAM))
+
+
+if __name__ == '__main__':
+    unittest.main()
@@ -0,0 +1,29 @@
+# Checks the helpers in `pycavy.testing` that users write their own
+# regression tests with. Run with `python -m unittest discover tests` once
+# pycavy is built and installed.
+
+import random
+import unittest
+
+from pycavy import Circuit
+from pycavy.testing import roundtrip
+
+from random_circuits import random_circuit
+
+
+class TestRoundtrip(unittest.TestCase):
+    def test_every_format_is_lossless(self):
+        circ = random_circuit(random.Random(0), 50)
+        for format in ('json', 'bytes', 'file'):
+            read = roundtrip(circ, format)
+            self.assertIsInstance(read, Circuit)
+            self.assertEqual(read.to_json(), circ.to_json())
+
+    def test_unknown_format(self):
+        circ = random_circuit(random.Random(1), 5)
+        with self.assertRaisesRegex(ValueError, 'json, bytes, file'):
+            roundtrip(circ, 'qasm3')
+
+
+if __name__ == '__main__':
+    unittest.main()
@@ -0,0 +1,64 @@
+# Checks that every twirled instance of a circuit is logically equivalent to
+# it: the same measurement statistics, and the same final state on each
+# branch, up to a global phase. Run with `python -m unittest discover tests`
+# once pycavy is built and installed.
+
+import json
+import random
+import unittest
+
+from pycavy import Circuit
+
+import statevector
+from random_circuits import random_circuit
+
+
+class TestTwirl(unittest.TestCase):
+    def assert_equivalent(self, circ: Circuit, twirled: Circuit):
+        expected = statevector.run(circ)
+        actual = statevector.run(twirled)
+        self.assertEqual(expected.keys(), actual.keys())
+        for record, (prob, state) in expected.items():
+            twirled_prob, twirled_state = actual[record]
+            self.assertAlmostEqual(prob, twirled_prob)
+            self.assertTrue(
+                statevector.equal_up_to_phase(state, twirled_state)
+            )
+
+    def test_unitary_circuits(self):
+        rng = random.Random(0)
+        for _ in range(10):
+            circ = random_circuit(rng, 20, measure=False)
+            seed = rng.randrange(2 ** 32)
+            for twirled in circ.twirl(instances=100, seed=seed):
+                self.assert_equivalent(circ, twirled)
+
+    def test_measured_circuits(self):
+        rng = random.Random(1)
+        for _ in range(10):
+            circ = random_circuit(rng, 20)
+            seed = rng.randrange(2 ** 32)
+            for twirled in circ.twirl(instances=100, seed=seed):
+                self.assert_equivalent(circ, twirled)
+
+    def test_twirls_two_qubit_cliffords(self):
+        circ = Circuit.from_json(json.dumps({
+            'version': 1,
+            'n_qubits': 2,
+            'instructions': [
+                {'kind': 'gate', 'gate': 'CX', 'qbs': [0, 1], 'ctrls': []},
+            ],
+        }))
+        twirled = circ.twirl(instances=100, seed=3)
+        lengths = {len(instance) for instance in twirled}
+        self.assertGreater(len(lengths), 1)
+
+    def test_seed_is_deterministic(self):
+        circ = random_circuit(random.Random(4), 20)
+        first = [t.to_json() for t in circ.twirl(instances=5, seed=5)]
+        second = [t.to_json() for t in circ.twirl(instances=5, seed=5)]
+        self.assertEqual(first, second)
+
+
+if __name__ == '__main__':
+    unittest.main()